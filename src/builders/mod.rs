@@ -1,9 +1,13 @@
 pub mod linktargets;
 pub mod page_maps;
 pub mod pagelinks;
+pub mod pipeline;
 pub mod redirect_targets;
+pub mod reorder;
 
 pub use linktargets::*;
 pub use page_maps::*;
 pub use pagelinks::*;
+pub use pipeline::*;
 pub use redirect_targets::*;
+pub use reorder::*;
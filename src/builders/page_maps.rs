@@ -1,19 +1,20 @@
 use crate::parsers::*;
+use crate::paths::{DataDir, Dumps};
 use crate::util;
 use rustc_hash::FxHashMap;
 
-pub fn build_and_save_page_maps_dense() -> anyhow::Result<()> {
+pub fn build_and_save_page_maps_dense(dumps: &Dumps, data: &DataDir) -> anyhow::Result<()> {
     let (orig_to_dense_id, dense_id_to_orig, title_to_dense_id, dense_id_to_title): (
         FxHashMap<u32, u32>,    // orig_to_dense_id
         Vec<u32>,               // dense_id_to_orig
         FxHashMap<String, u32>, // title_to_dense_id
         Vec<String>,            // dense_id_to_title
-    ) = build_title_maps_dense("../sql_files/enwiki-latest-page.sql.gz")?;
+    ) = build_title_maps_dense(&dumps.table("page"))?;
 
-    util::save_to_file(&orig_to_dense_id, "data/orig_to_dense_id.bin")?;
-    util::save_to_file(&dense_id_to_orig, "data/dense_id_to_orig.bin")?;
-    util::save_to_file(&title_to_dense_id, "data/title_to_dense_id.bin")?;
-    util::save_to_file(&dense_id_to_title, "data/dense_id_to_title.bin")?;
+    util::save_to_file(&orig_to_dense_id, &data.file("orig_to_dense_id.bin"))?;
+    util::save_to_file(&dense_id_to_orig, &data.file("dense_id_to_orig.bin"))?;
+    util::save_to_file(&title_to_dense_id, &data.file("title_to_dense_id.bin"))?;
+    util::save_to_file(&dense_id_to_title, &data.file("dense_id_to_title.bin"))?;
 
     Ok(())
 }
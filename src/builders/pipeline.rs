@@ -0,0 +1,569 @@
+// src/builders/pipeline.rs
+// the build as a list of stages with their input and output files, so a rebuild only redoes
+// what's out of date. what was built from what goes in manifest.json in the data dir
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::builders::*;
+use crate::mmap_structs::*;
+use crate::paths::{DataDir, Dumps};
+use crate::util;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// One step of the build, in the order they run
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    PageMaps,
+    Linktargets,
+    RedirectTargets,
+    Pagelinks,
+    /// Only part of the build with --reorder
+    Reorder,
+    Csr,
+    /// Only part of the build once csr_compressed/ is there, or with --stage compressed-csr
+    CompressedCsr,
+    TitleToDenseId,
+    DenseIdToTitle,
+    OrigToDenseId,
+    DenseIdToOrig,
+    RedirectsPassed,
+    RedirectTargetsMmap,
+    Scc,
+    Landmarks,
+}
+
+const ALL_STAGES: [Stage; 15] = [
+    Stage::PageMaps,
+    Stage::Linktargets,
+    Stage::RedirectTargets,
+    Stage::Pagelinks,
+    Stage::Reorder,
+    Stage::Csr,
+    Stage::CompressedCsr,
+    Stage::TitleToDenseId,
+    Stage::DenseIdToTitle,
+    Stage::OrigToDenseId,
+    Stage::DenseIdToOrig,
+    Stage::RedirectsPassed,
+    Stage::RedirectTargetsMmap,
+    Stage::Scc,
+    Stage::Landmarks,
+];
+
+const CSR_FILES: &[&str] = &[
+    "csr/edges.bin",
+    "csr/reverse_edges.bin",
+    "csr/offsets.bin",
+    "csr/reverse_offsets.bin",
+];
+
+// every intermediate file, the reorder stage relabels them all in place
+const REORDERED_FILES: &[&str] = &[
+    "pagelinks_adjacency_list.bin",
+    "incoming_pagelinks_adjacency_list.bin",
+    "orig_to_dense_id.bin",
+    "dense_id_to_orig.bin",
+    "dense_id_to_title.bin",
+    "title_to_dense_id.bin",
+    "redirect_targets_dense.bin",
+    "redirects_passed_dense.bin",
+    "linktargets_dense.bin",
+];
+
+/// What a stage reads and writes. Dumps are table names, the rest are paths in the data dir.
+pub struct StageFiles {
+    pub dumps: &'static [&'static str],
+    pub inputs: &'static [&'static str],
+    pub outputs: &'static [&'static str],
+    // files it reads and writes back in place
+    pub rewrites: &'static [&'static str],
+}
+
+impl Stage {
+    pub fn files(self) -> StageFiles {
+        let (dumps, inputs, outputs, rewrites): (&[&str], &[&str], &[&str], &[&str]) = match self {
+            Stage::PageMaps => (
+                &["page"],
+                &[],
+                &[
+                    "orig_to_dense_id.bin",
+                    "dense_id_to_orig.bin",
+                    "title_to_dense_id.bin",
+                    "dense_id_to_title.bin",
+                ],
+                &[],
+            ),
+            Stage::Linktargets => (
+                &["linktarget"],
+                &["title_to_dense_id.bin"],
+                &["linktargets_dense.bin"],
+                &[],
+            ),
+            Stage::RedirectTargets => (
+                &["redirect"],
+                &["title_to_dense_id.bin", "orig_to_dense_id.bin"],
+                &["redirect_targets_dense.bin"],
+                &[],
+            ),
+            Stage::Pagelinks => (
+                &["pagelinks"],
+                &[
+                    "linktargets_dense.bin",
+                    "redirect_targets_dense.bin",
+                    "orig_to_dense_id.bin",
+                ],
+                &[
+                    "pagelinks_adjacency_list.bin",
+                    "incoming_pagelinks_adjacency_list.bin",
+                    "redirects_passed_dense.bin",
+                ],
+                &[],
+            ),
+            Stage::Reorder => (
+                &[],
+                REORDERED_FILES,
+                &["reorder/old_to_new.bin"],
+                REORDERED_FILES,
+            ),
+            Stage::Csr => (
+                &[],
+                &[
+                    "pagelinks_adjacency_list.bin",
+                    "incoming_pagelinks_adjacency_list.bin",
+                    "orig_to_dense_id.bin",
+                ],
+                &[
+                    "pagelinks_csr.bin",
+                    "csr/edges.bin",
+                    "csr/reverse_edges.bin",
+                    "csr/offsets.bin",
+                    "csr/reverse_offsets.bin",
+                ],
+                &[],
+            ),
+            Stage::CompressedCsr => (
+                &[],
+                CSR_FILES,
+                &[
+                    "csr_compressed/offsets.bin",
+                    "csr_compressed/edges.bin",
+                    "csr_compressed/reverse_offsets.bin",
+                    "csr_compressed/reverse_edges.bin",
+                    "csr_compressed/num_edges.bin",
+                ],
+                &[],
+            ),
+            Stage::TitleToDenseId => (
+                &[],
+                &["title_to_dense_id.bin"],
+                &[
+                    "title_to_dense_id/titles.bin",
+                    "title_to_dense_id/offsets.bin",
+                    "title_to_dense_id/dense_ids.bin",
+                ],
+                &[],
+            ),
+            Stage::DenseIdToTitle => (
+                &[],
+                &["dense_id_to_title.bin"],
+                &[
+                    "dense_id_to_title/titles.bin",
+                    "dense_id_to_title/offsets.bin",
+                ],
+                &[],
+            ),
+            Stage::OrigToDenseId => (
+                &[],
+                &["orig_to_dense_id.bin"],
+                &[
+                    "orig_to_dense_id/orig_ids.bin",
+                    "orig_to_dense_id/dense_ids.bin",
+                ],
+                &[],
+            ),
+            Stage::DenseIdToOrig => (
+                &[],
+                &["dense_id_to_orig.bin"],
+                &["dense_id_to_orig/mmap.bin"],
+                &[],
+            ),
+            Stage::RedirectsPassed => (
+                &[],
+                &["redirects_passed_dense.bin", "dense_id_to_orig.bin"],
+                &[
+                    "redirects_passed/offsets.bin",
+                    "redirects_passed/redirect_targets.bin",
+                    "redirects_passed/redirects.bin",
+                ],
+                &[],
+            ),
+            Stage::RedirectTargetsMmap => (
+                &[],
+                &["redirect_targets_dense.bin"],
+                &["redirect_targets_dense/redirect_targets_dense.bin"],
+                &[],
+            ),
+            Stage::Scc => (
+                &[],
+                CSR_FILES,
+                &[
+                    "scc/component_ids.bin",
+                    "scc/dag/offsets.bin",
+                    "scc/dag/edges.bin",
+                    "scc/dag/reverse_offsets.bin",
+                    "scc/dag/reverse_edges.bin",
+                ],
+                &[],
+            ),
+            Stage::Landmarks => (
+                &[],
+                CSR_FILES,
+                &[
+                    "landmarks/landmarks.bin",
+                    "landmarks/dist_from.bin",
+                    "landmarks/dist_to.bin",
+                ],
+                &[],
+            ),
+        };
+        StageFiles {
+            dumps,
+            inputs,
+            outputs,
+            rewrites,
+        }
+    }
+
+    fn run(
+        self,
+        dumps: &Dumps,
+        data: &DataDir,
+        reorder: Option<ReorderStrategy>,
+    ) -> anyhow::Result<()> {
+        match self {
+            Stage::PageMaps => build_and_save_page_maps_dense(dumps, data),
+            Stage::Linktargets => build_and_save_linktargets_dense(dumps, data),
+            Stage::RedirectTargets => build_and_save_redirect_targets_dense(dumps, data),
+            Stage::Pagelinks => build_and_save_pagelinks_adj_list(dumps, data),
+            Stage::Reorder => match reorder {
+                Some(strategy) => build_and_save_reordered_ids(data, strategy),
+                None => anyhow::bail!("the reorder stage needs --reorder"),
+            },
+            Stage::Csr => build_and_save_pagelinks_csr(data),
+            Stage::CompressedCsr => build_and_save_compressed_csr_mmap(data),
+            Stage::TitleToDenseId => build_and_save_title_to_dense_id_mmap(data),
+            Stage::DenseIdToTitle => build_and_save_dense_id_to_title_mmap(data),
+            Stage::OrigToDenseId => build_and_save_orig_to_dense_id_mmap(data),
+            Stage::DenseIdToOrig => build_and_save_dense_id_to_orig_mmap(data),
+            Stage::RedirectsPassed => build_and_save_redirects_passed_mmap(data),
+            Stage::RedirectTargetsMmap => build_and_save_redirect_targets_dense_mmap(data),
+            Stage::Scc => build_and_save_scc_mmap(data),
+            Stage::Landmarks => build_and_save_landmarks_mmap(data, DEFAULT_NUM_LANDMARKS),
+        }
+    }
+}
+
+/// Size and modification time, cheap enough to check on the dumps too
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub modified_ns: u64,
+}
+
+impl Fingerprint {
+    fn of(path: &str) -> Option<Fingerprint> {
+        let meta = fs::metadata(path).ok()?;
+        let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Fingerprint {
+            size: meta.len(),
+            modified_ns: modified.as_nanos() as u64,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageRecord {
+    // manifest.runs when it finished, a stage built before its inputs' stage is out of date
+    pub run: u64,
+    pub finished_at: String,
+    // keyed by dump file name or path in the data dir
+    pub inputs: BTreeMap<String, Fingerprint>,
+    #[serde(default)]
+    pub outputs: BTreeMap<String, Fingerprint>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    // util::FORMAT_VERSION the files were written with, a different one starts over
+    #[serde(default)]
+    pub format_version: u32,
+    pub wiki: String,
+    // when the page dump file was last modified, so when it was downloaded or copied.
+    // the -latest- dump names don't say which dump it is
+    #[serde(alias = "dump_date")]
+    pub page_dump_modified: Option<String>,
+    pub reorder: Option<ReorderStrategy>,
+    // when dense ids were last handed out (page-maps or reorder), files numbered before
+    // don't fit the rest. a time rather than a run, runs start over with the manifest
+    #[serde(default)]
+    pub numbered_at: Option<String>,
+    // set while a stage is building, its files can't be trusted until it's cleared
+    pub running: Option<Stage>,
+    pub runs: u64,
+    pub stages: BTreeMap<Stage, StageRecord>,
+}
+
+impl Manifest {
+    /// The data dir's manifest, none if it was never built with the pipeline
+    pub fn load(data: &DataDir) -> anyhow::Result<Option<Manifest>> {
+        let path = data.file(MANIFEST_FILE);
+        let text = match data.bundle() {
+            Some(bundle) => match bundle.section(MANIFEST_FILE) {
+                Some((offset, len)) => {
+                    String::from_utf8(bundle.mmap[offset..offset + len].to_vec())?
+                }
+                None => return Ok(None),
+            },
+            None if !Path::new(&path).exists() => return Ok(None),
+            None => fs::read_to_string(&path)?,
+        };
+        let manifest = serde_json::from_str(&text).with_context(|| format!("reading {}", path))?;
+        Ok(Some(manifest))
+    }
+
+    fn save(&self, data: &DataDir) -> anyhow::Result<()> {
+        util::write_atomically(&serde_json::to_vec_pretty(self)?, &data.file(MANIFEST_FILE))?;
+        data.set_dataset_id(self.dataset_id());
+        Ok(())
+    }
+
+    /// Hash of the wiki, the page dump time and the numbering, every mmap file has the one
+    /// of the build that wrote it
+    pub fn dataset_id(&self) -> u64 {
+        util::checksum(
+            format!(
+                "{}|{}|{}",
+                self.wiki,
+                self.page_dump_modified.as_deref().unwrap_or_default(),
+                self.numbered_at.as_deref().unwrap_or_default()
+            )
+            .as_bytes(),
+        )
+    }
+}
+
+fn dump_file_name(dumps: &Dumps, table: &str) -> String {
+    format!("{}-latest-{}.sql.gz", dumps.wiki, table)
+}
+
+/// Run every stage that's out of date, plus `force` and everything after it that depends on it.
+/// A stage is out of date when it was never built, an output is missing or was changed,
+/// a dump or input file changed since, or a stage it reads from was built again after it.
+pub fn run_build(
+    dumps: &Dumps,
+    data: &DataDir,
+    reorder: Option<ReorderStrategy>,
+    force: Option<Stage>,
+) -> anyhow::Result<()> {
+    println!("Building {} into {}...", dumps.wiki, data.path().display());
+    if data.bundle().is_some() {
+        anyhow::bail!(
+            "{} is a bundle, build into a folder and convert that",
+            data.path().display()
+        );
+    }
+    if force == Some(Stage::Reorder) && reorder.is_none() {
+        anyhow::bail!("--stage reorder needs --reorder");
+    }
+
+    let mut manifest = match Manifest::load(data) {
+        Ok(Some(manifest))
+            if manifest.wiki == dumps.wiki
+                && manifest.reorder == reorder
+                && manifest.format_version == util::FORMAT_VERSION =>
+        {
+            manifest
+        }
+        Ok(Some(_)) => {
+            println!("built for another wiki, reorder strategy or file format, starting over");
+            Manifest::default()
+        }
+        Ok(None) => Manifest::default(),
+        Err(e) => {
+            println!("unreadable manifest, starting over: {:#}", e);
+            Manifest::default()
+        }
+    };
+    manifest.format_version = util::FORMAT_VERSION;
+    manifest.wiki = dumps.wiki.clone();
+    manifest.reorder = reorder;
+
+    // the compressed graph is optional, but once it's there it has to follow csr/
+    let compressed_csr =
+        force == Some(Stage::CompressedCsr) || Path::new(&data.file("csr_compressed")).exists();
+    let stages: Vec<Stage> = ALL_STAGES
+        .into_iter()
+        .filter(|&stage| stage != Stage::Reorder || reorder.is_some())
+        .filter(|&stage| stage != Stage::CompressedCsr || compressed_csr)
+        .collect();
+    // the last stage before `before` that writes the file
+    let producer = |file: &str, before: usize| -> Option<usize> {
+        (0..before).rev().find(|&i| {
+            let files = stages[i].files();
+            files.outputs.contains(&file) || files.rewrites.contains(&file)
+        })
+    };
+    // inputs some stage from `from` on rewrites, their fingerprints change after this stage ran
+    let rewritten_later = |file: &str, from: usize| -> bool {
+        stages[from..]
+            .iter()
+            .any(|stage| stage.files().rewrites.contains(&file))
+    };
+
+    let mut stale: BTreeSet<usize> = BTreeSet::new();
+    for (i, &stage) in stages.iter().enumerate() {
+        let files = stage.files();
+        let Some(record) = manifest.stages.get(&stage) else {
+            stale.insert(i);
+            continue;
+        };
+        // missing, or changed by something other than this build
+        let output_changed = files.outputs.iter().any(|file| {
+            let fingerprint = Fingerprint::of(&data.file(file));
+            fingerprint.is_none()
+                || (!rewritten_later(file, i + 1)
+                    && record.outputs.get(*file) != fingerprint.as_ref())
+        });
+        let dump_changed = files.dumps.iter().any(|table| {
+            record.inputs.get(&dump_file_name(dumps, table))
+                != Fingerprint::of(&dumps.table(table)).as_ref()
+        });
+        let input_changed = files.inputs.iter().any(|file| {
+            !rewritten_later(file, i)
+                && record.inputs.get(*file) != Fingerprint::of(&data.file(file)).as_ref()
+        });
+        if force == Some(stage) || output_changed || dump_changed || input_changed {
+            stale.insert(i);
+        }
+    }
+
+    // a stage is out of date if what it reads is, and a stage reading files that get rewritten
+    // later needs them from scratch, so their producers go too. repeat until nothing changes
+    loop {
+        let before = stale.len();
+        for (i, &stage) in stages.iter().enumerate() {
+            let files = stage.files();
+            let record = manifest.stages.get(&stage);
+            for file in files.inputs {
+                let Some(p) = producer(file, i) else {
+                    continue;
+                };
+                let rebuilt_since = match (manifest.stages.get(&stages[p]), record) {
+                    (Some(produced), Some(record)) => produced.run > record.run,
+                    _ => true,
+                };
+                if stale.contains(&p) || rebuilt_since {
+                    stale.insert(i);
+                }
+                if stale.contains(&i) && rewritten_later(file, i) {
+                    stale.insert(p);
+                }
+            }
+        }
+        if stale.len() == before {
+            break;
+        }
+    }
+
+    // fail before hours of parsing rather than after
+    for &i in &stale {
+        for table in stages[i].files().dumps {
+            let path = dumps.table(table);
+            if !Path::new(&path).exists() {
+                anyhow::bail!("missing dump {}", path);
+            }
+        }
+    }
+
+    for (i, &stage) in stages.iter().enumerate() {
+        if !stale.contains(&i) {
+            println!("{:?} is up to date", stage);
+            continue;
+        }
+        let files = stage.files();
+        println!("building {:?}", stage);
+        manifest.stages.remove(&stage);
+        manifest.running = Some(stage);
+        // every file with dense ids is built again after these
+        if matches!(stage, Stage::PageMaps | Stage::Reorder) {
+            manifest.numbered_at = Some(Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true));
+        }
+        manifest.save(data)?;
+
+        // the old outputs were built from other inputs, don't let them outlive a failed run
+        remove_files(data, files.outputs);
+        if let Err(e) = stage.run(dumps, data, reorder) {
+            remove_files(data, files.outputs);
+            remove_files(data, files.rewrites);
+            manifest.running = None;
+            manifest.save(data)?;
+            return Err(e.context(format!("building {:?}", stage)));
+        }
+
+        let mut inputs = BTreeMap::new();
+        for table in files.dumps {
+            if let Some(fingerprint) = Fingerprint::of(&dumps.table(table)) {
+                inputs.insert(dump_file_name(dumps, table), fingerprint);
+            }
+        }
+        for file in files.inputs {
+            if let Some(fingerprint) = Fingerprint::of(&data.file(file)) {
+                inputs.insert(file.to_string(), fingerprint);
+            }
+        }
+        let outputs = files
+            .outputs
+            .iter()
+            .filter_map(|file| Some((file.to_string(), Fingerprint::of(&data.file(file))?)))
+            .collect();
+        if stage == Stage::PageMaps {
+            manifest.page_dump_modified = fs::metadata(dumps.table("page"))
+                .and_then(|meta| meta.modified())
+                .ok()
+                .map(|modified| DateTime::<Utc>::from(modified).to_rfc3339());
+        }
+        manifest.runs += 1;
+        manifest.stages.insert(
+            stage,
+            StageRecord {
+                run: manifest.runs,
+                finished_at: Utc::now().to_rfc3339(),
+                inputs,
+                outputs,
+            },
+        );
+        manifest.running = None;
+        manifest.save(data)?;
+    }
+    Ok(())
+}
+
+/// Remove the files and any temp files left from writing them
+fn remove_files(data: &DataDir, files: &[&str]) {
+    for file in files {
+        let path = data.file(file);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(format!("{}.tmp", path));
+    }
+}
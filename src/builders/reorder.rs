@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::graph::*;
+use crate::paths::DataDir;
+use crate::search::{bi_bfs_csr, SearchLimits};
+use crate::search_context::SearchContext;
+use crate::util;
+
+// random pairs timed on the graph before and after relabeling
+const BENCHMARK_PAIRS: usize = 200;
+
+/// How dense ids get renumbered so pages that link to each other end up close in `edges.bin`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ReorderStrategy {
+    /// Breadth-first order from the most linked page
+    Bfs,
+    /// Reverse Cuthill-McKee: bfs from low degree pages, neighbours by degree, then reversed
+    Rcm,
+    /// Most linked pages first
+    Degree,
+}
+
+/// Relabel the dense ids in every intermediate file, between building the adjacency lists
+/// and building the csr/mmap files, so everything after it is consistent. The permutation
+/// (old dense id -> new dense id) is saved to reorder/old_to_new.bin in the data dir.
+pub fn build_and_save_reordered_ids(
+    data: &DataDir,
+    strategy: ReorderStrategy,
+) -> anyhow::Result<()> {
+    let adjacency_list: FxHashMap<u32, Vec<u32>> =
+        util::load_from_file(&data.file("pagelinks_adjacency_list.bin"))?;
+    let reverse_adjacency_list: FxHashMap<u32, Vec<u32>> =
+        util::load_from_file(&data.file("incoming_pagelinks_adjacency_list.bin"))?;
+    let orig_to_dense_id: FxHashMap<u32, u32> =
+        util::load_from_file(&data.file("orig_to_dense_id.bin"))?;
+
+    println!("computing {:?} order", strategy);
+    let csr_before =
+        build_csr_with_adjacency_list(&orig_to_dense_id, &adjacency_list, &reverse_adjacency_list);
+    let order = match strategy {
+        ReorderStrategy::Bfs => bfs_order(&csr_before),
+        ReorderStrategy::Rcm => rcm_order(&csr_before),
+        ReorderStrategy::Degree => degree_order(&csr_before),
+    };
+    let mut old_to_new = vec![0u32; order.len()];
+    for (new_id, &old_id) in order.iter().enumerate() {
+        old_to_new[old_id as usize] = new_id as u32;
+    }
+    drop(order);
+
+    let mut rng = rand::rng();
+    let num_nodes = csr_before.num_nodes();
+    let pairs: Vec<(u32, u32)> = (0..BENCHMARK_PAIRS)
+        .map(|_| {
+            (
+                rng.random_range(0..num_nodes) as u32,
+                rng.random_range(0..num_nodes) as u32,
+            )
+        })
+        .collect();
+    let before = time_searches(&csr_before, &pairs);
+    drop(csr_before);
+
+    let relabel = |id: u32| old_to_new[id as usize];
+    let relabel_adjacency_list = |list: FxHashMap<u32, Vec<u32>>| -> FxHashMap<u32, Vec<u32>> {
+        list.into_iter()
+            .map(|(node, neighbors)| (relabel(node), neighbors.into_iter().map(relabel).collect()))
+            .collect()
+    };
+    let adjacency_list = relabel_adjacency_list(adjacency_list);
+    let reverse_adjacency_list = relabel_adjacency_list(reverse_adjacency_list);
+
+    let csr_after =
+        build_csr_with_adjacency_list(&orig_to_dense_id, &adjacency_list, &reverse_adjacency_list);
+    let relabeled_pairs: Vec<(u32, u32)> = pairs
+        .iter()
+        .map(|&(start, goal)| (relabel(start), relabel(goal)))
+        .collect();
+    let after = time_searches(&csr_after, &relabeled_pairs);
+    drop(csr_after);
+    println!(
+        "{} searches: {:.2?} before, {:.2?} after {:?} order",
+        BENCHMARK_PAIRS, before, after, strategy
+    );
+
+    println!("relabeling intermediate files");
+    util::save_to_file(&adjacency_list, &data.file("pagelinks_adjacency_list.bin"))?;
+    util::save_to_file(
+        &reverse_adjacency_list,
+        &data.file("incoming_pagelinks_adjacency_list.bin"),
+    )?;
+    drop(adjacency_list);
+    drop(reverse_adjacency_list);
+
+    let orig_to_dense_id: FxHashMap<u32, u32> = orig_to_dense_id
+        .into_iter()
+        .map(|(orig_id, dense_id)| (orig_id, relabel(dense_id)))
+        .collect();
+    util::save_to_file(&orig_to_dense_id, &data.file("orig_to_dense_id.bin"))?;
+    drop(orig_to_dense_id);
+
+    let dense_id_to_orig: Vec<u32> = util::load_from_file(&data.file("dense_id_to_orig.bin"))?;
+    util::save_to_file(
+        &permute(dense_id_to_orig, &old_to_new),
+        &data.file("dense_id_to_orig.bin"),
+    )?;
+
+    let dense_id_to_title: Vec<String> = util::load_from_file(&data.file("dense_id_to_title.bin"))?;
+    util::save_to_file(
+        &permute(dense_id_to_title, &old_to_new),
+        &data.file("dense_id_to_title.bin"),
+    )?;
+
+    let title_to_dense_id: FxHashMap<String, u32> =
+        util::load_from_file(&data.file("title_to_dense_id.bin"))?;
+    let title_to_dense_id: FxHashMap<String, u32> = title_to_dense_id
+        .into_iter()
+        .map(|(title, dense_id)| (title, relabel(dense_id)))
+        .collect();
+    util::save_to_file(&title_to_dense_id, &data.file("title_to_dense_id.bin"))?;
+    drop(title_to_dense_id);
+
+    // indexed by dense id, u32::MAX for pages that aren't redirects
+    let redirect_targets_dense: Vec<u32> =
+        util::load_from_file(&data.file("redirect_targets_dense.bin"))?;
+    let redirect_targets_dense: Vec<u32> = permute(redirect_targets_dense, &old_to_new)
+        .into_iter()
+        .map(|target| match target {
+            u32::MAX => u32::MAX,
+            target => relabel(target),
+        })
+        .collect();
+    util::save_to_file(
+        &redirect_targets_dense,
+        &data.file("redirect_targets_dense.bin"),
+    )?;
+
+    let redirects_passed_dense: FxHashMap<(u32, u32), u32> =
+        util::load_from_file(&data.file("redirects_passed_dense.bin"))?;
+    let redirects_passed_dense: FxHashMap<(u32, u32), u32> = redirects_passed_dense
+        .into_iter()
+        .map(|((page_from, target), redirect)| {
+            ((relabel(page_from), relabel(target)), relabel(redirect))
+        })
+        .collect();
+    util::save_to_file(
+        &redirects_passed_dense,
+        &data.file("redirects_passed_dense.bin"),
+    )?;
+
+    // linktarget id -> dense id
+    let linktargets_dense: FxHashMap<u32, u32> =
+        util::load_from_file(&data.file("linktargets_dense.bin"))?;
+    let linktargets_dense: FxHashMap<u32, u32> = linktargets_dense
+        .into_iter()
+        .map(|(linktarget_id, dense_id)| (linktarget_id, relabel(dense_id)))
+        .collect();
+    util::save_to_file(&linktargets_dense, &data.file("linktargets_dense.bin"))?;
+
+    util::write_u32_vec_to_file(&old_to_new, data, "reorder/old_to_new.bin")?;
+
+    Ok(())
+}
+
+/// values[old_id] moved to [old_to_new[old_id]]
+fn permute<T: Default + Clone>(values: Vec<T>, old_to_new: &[u32]) -> Vec<T> {
+    let mut permuted = vec![T::default(); values.len()];
+    for (old_id, value) in values.into_iter().enumerate() {
+        permuted[old_to_new[old_id] as usize] = value;
+    }
+    permuted
+}
+
+fn degree<G: CsrGraphTrait>(graph: &G, node: u32) -> usize {
+    graph.degree(node) + graph.reverse_degree(node)
+}
+
+fn degree_order<G: CsrGraphTrait>(graph: &G) -> Vec<u32> {
+    let mut order: Vec<u32> = (0..graph.num_nodes() as u32).collect();
+    order.sort_unstable_by_key(|&node| (std::cmp::Reverse(degree(graph, node)), node));
+    order
+}
+
+fn bfs_order<G: CsrGraphTrait>(graph: &G) -> Vec<u32> {
+    // links are followed both ways, so a page lands near the pages linking to it too
+    let roots = degree_order(graph);
+    let mut visited = vec![false; graph.num_nodes()];
+    let mut order = Vec::with_capacity(graph.num_nodes());
+    let mut queue: VecDeque<u32> = VecDeque::new();
+    for root in roots {
+        if visited[root as usize] {
+            continue;
+        }
+        visited[root as usize] = true;
+        queue.push_back(root);
+        while let Some(node) = queue.pop_front() {
+            order.push(node);
+            for neighbor in graph.neighbors(node).chain(graph.reverse_neighbors(node)) {
+                if !visited[neighbor as usize] {
+                    visited[neighbor as usize] = true;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+    order
+}
+
+fn rcm_order<G: CsrGraphTrait>(graph: &G) -> Vec<u32> {
+    // every component starts from its lowest degree page, a cheap stand-in for a peripheral one
+    let mut roots = degree_order(graph);
+    roots.reverse();
+    let mut visited = vec![false; graph.num_nodes()];
+    let mut order = Vec::with_capacity(graph.num_nodes());
+    let mut queue: VecDeque<u32> = VecDeque::new();
+    let mut neighbors: Vec<u32> = Vec::new();
+    for root in roots {
+        if visited[root as usize] {
+            continue;
+        }
+        visited[root as usize] = true;
+        queue.push_back(root);
+        while let Some(node) = queue.pop_front() {
+            order.push(node);
+            neighbors.clear();
+            for neighbor in graph.neighbors(node).chain(graph.reverse_neighbors(node)) {
+                if !visited[neighbor as usize] {
+                    visited[neighbor as usize] = true;
+                    neighbors.push(neighbor);
+                }
+            }
+            neighbors.sort_unstable_by_key(|&neighbor| (degree(graph, neighbor), neighbor));
+            queue.extend(&neighbors);
+        }
+    }
+    order.reverse();
+    order
+}
+
+fn time_searches<G: CsrGraphTrait>(graph: &G, pairs: &[(u32, u32)]) -> Duration {
+    let mut ctx = SearchContext::new(graph.num_nodes());
+    let limits = SearchLimits::with_max_depth(50);
+    let start_time = Instant::now();
+    for &(start, goal) in pairs {
+        let mut node_count = 0;
+        let _ = bi_bfs_csr(graph, &mut ctx, start, goal, &limits, &mut node_count);
+    }
+    start_time.elapsed()
+}
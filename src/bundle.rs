@@ -0,0 +1,189 @@
+// src/bundle.rs
+// every mmap file of a data dir packed into one file, so a machine only needs one file copied.
+// a DataDir opened on a bundle hands out views into its one mmap instead of mapping each file
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use memmap2::Mmap;
+
+use crate::builders::{Manifest, MANIFEST_FILE};
+use crate::mmap_structs::{load_csr_graph_mmap, load_or_build_hub_degrees_mmap};
+use crate::paths::DataDir;
+use crate::util;
+
+const BUNDLE_MAGIC: &[u8; 8] = b"WGBUNDLE";
+const BUNDLE_VERSION: u32 = 1;
+// magic, version, number of sections
+const BUNDLE_HEADER_LEN: usize = 16;
+// name padded with zeros, then its offset and length as u64s
+const TOC_ENTRY_LEN: usize = 128;
+const MAX_NAME_LEN: usize = TOC_ENTRY_LEN - 16;
+// sections start on a page, the data after each file header stays aligned
+const SECTION_ALIGN: u64 = 4096;
+
+/// An opened bundle, the table of contents points into one mmap
+#[derive(Debug)]
+pub struct Bundle {
+    pub mmap: Arc<Mmap>,
+    // name in the data dir -> (offset, length) of the whole file, header included
+    sections: BTreeMap<String, (usize, usize)>,
+}
+
+impl Bundle {
+    pub fn open(path: &Path) -> anyhow::Result<Bundle> {
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        // Safety: mapping readonly file
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() < BUNDLE_HEADER_LEN || &mmap[0..8] != BUNDLE_MAGIC {
+            anyhow::bail!("{} isn't a bundle", path.display());
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(mmap[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(mmap[i..i + 8].try_into().unwrap());
+        if u32_at(8) != BUNDLE_VERSION {
+            anyhow::bail!(
+                "{} is bundle version {}, this build reads {}",
+                path.display(),
+                u32_at(8),
+                BUNDLE_VERSION
+            );
+        }
+
+        let num_sections = u32_at(12) as usize;
+        if mmap.len() < BUNDLE_HEADER_LEN + num_sections * TOC_ENTRY_LEN {
+            anyhow::bail!("{} is truncated", path.display());
+        }
+        let mut sections = BTreeMap::new();
+        for i in 0..num_sections {
+            let entry = BUNDLE_HEADER_LEN + i * TOC_ENTRY_LEN;
+            let name_bytes = &mmap[entry..entry + MAX_NAME_LEN];
+            let name_len = name_bytes
+                .iter()
+                .position(|&b| b == 0)
+                .unwrap_or(MAX_NAME_LEN);
+            let name = std::str::from_utf8(&name_bytes[..name_len])?.to_string();
+            let offset = u64_at(entry + MAX_NAME_LEN) as usize;
+            let len = u64_at(entry + MAX_NAME_LEN + 8) as usize;
+            if !offset.is_multiple_of(SECTION_ALIGN as usize) || offset + len > mmap.len() {
+                anyhow::bail!("{} in {} is out of bounds", name, path.display());
+            }
+            sections.insert(name, (offset, len));
+        }
+        Ok(Bundle {
+            mmap: Arc::new(mmap),
+            sections,
+        })
+    }
+
+    /// Offset and length of a file in the bundle
+    pub fn section(&self, name: &str) -> Option<(usize, usize)> {
+        self.sections.get(name).copied()
+    }
+
+    pub fn sections(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.sections
+            .iter()
+            .map(|(name, &(offset, len))| (name.as_str(), &self.mmap[offset..offset + len]))
+    }
+}
+
+/// Pack every mmap file in `data` plus its manifest into one bundle at `out`
+pub fn write_bundle(data: &DataDir, out: &Path) -> anyhow::Result<()> {
+    if let Some(stage) = Manifest::load(data)?.and_then(|manifest| manifest.running) {
+        anyhow::bail!(
+            "the build in {} stopped during {:?}, run it again first",
+            data.path().display(),
+            stage
+        );
+    }
+    // the server computes the hub table on start, which it can't do inside a bundle
+    load_or_build_hub_degrees_mmap(data, &load_csr_graph_mmap(data)?)?;
+
+    let mut names: Vec<String> = Vec::new();
+    let mut dirs = vec![data.path().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let name = path
+                .strip_prefix(data.path())?
+                .to_string_lossy()
+                .replace('\\', "/");
+            // the bitcode files are only read by the builders
+            if name == MANIFEST_FILE || (!name.ends_with(".tmp") && util::has_file_header(&path)?) {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+
+    let table_end = (BUNDLE_HEADER_LEN + names.len() * TOC_ENTRY_LEN) as u64;
+    let mut offset = table_end.next_multiple_of(SECTION_ALIGN);
+    let mut entries: Vec<(&str, u64, u64)> = Vec::with_capacity(names.len());
+    for name in &names {
+        if name.len() > MAX_NAME_LEN {
+            anyhow::bail!("{} is too long a name for a bundle", name);
+        }
+        let len = fs::metadata(data.file(name))?.len();
+        entries.push((name, offset, len));
+        offset = (offset + len).next_multiple_of(SECTION_ALIGN);
+    }
+
+    println!(
+        "packing {} files from {} into {}",
+        entries.len(),
+        data.path().display(),
+        out.display()
+    );
+    // written next to it and renamed, like the files it packs
+    let tmp_path = PathBuf::from(format!("{}.tmp", out.display()));
+    let mut writer = BufWriter::with_capacity(1 << 20, File::create(&tmp_path)?);
+    writer.write_all(BUNDLE_MAGIC)?;
+    writer.write_all(&BUNDLE_VERSION.to_le_bytes())?;
+    writer.write_all(&(entries.len() as u32).to_le_bytes())?;
+    for &(name, offset, len) in &entries {
+        let mut entry = [0u8; TOC_ENTRY_LEN];
+        entry[..name.len()].copy_from_slice(name.as_bytes());
+        entry[MAX_NAME_LEN..MAX_NAME_LEN + 8].copy_from_slice(&offset.to_le_bytes());
+        entry[MAX_NAME_LEN + 8..].copy_from_slice(&len.to_le_bytes());
+        writer.write_all(&entry)?;
+    }
+    let mut written = table_end;
+    for &(name, offset, len) in &entries {
+        writer.write_all(&vec![0u8; (offset - written) as usize])?;
+        let mut file = File::open(data.file(name))?;
+        io::copy(&mut file, &mut writer)?;
+        written = offset + len;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&tmp_path, out)?;
+    Ok(())
+}
+
+/// Write every file in the bundle back out under `out_dir`
+pub fn unpack_bundle(bundle: &Bundle, out_dir: &DataDir) -> anyhow::Result<()> {
+    for (name, bytes) in bundle.sections() {
+        println!("writing {}", out_dir.file(name));
+        util::write_atomically(bytes, &out_dir.file(name))?;
+    }
+    Ok(())
+}
+
+/// A data dir becomes a bundle file, a bundle file becomes a data dir
+pub fn run_convert(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if from.is_dir() {
+        write_bundle(&DataDir::new(from), to)
+    } else {
+        unpack_bundle(&Bundle::open(from)?, &DataDir::new(to))
+    }
+}
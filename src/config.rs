@@ -4,6 +4,8 @@ use anyhow::bail;
 use dotenv::dotenv;
 use std::env;
 
+use crate::paths::{DataDir, DEFAULT_WIKI};
+
 #[derive(Clone, Debug)]
 pub struct EnvironmentVariables {
     pub jwt_secret: String,
@@ -14,6 +16,59 @@ pub struct EnvironmentVariables {
     pub frontend_origin: String,
     pub port: u16,
     pub is_production: bool,
+    pub search_threads: usize,
+    pub search_queue_limit: usize,
+    pub search_timeout_ms: u64,
+    // no node budget unless set
+    pub search_max_nodes: Option<u32>,
+    // search csr_compressed/ instead of csr/
+    pub compressed_graph: bool,
+    // (wiki, data dir) of every wiki served, the first one is the default
+    pub datasets: Vec<(String, DataDir)>,
+}
+
+/// GRAPH_BACKEND=plain (default) or compressed, also read by the cli tools
+pub fn compressed_graph_from_env() -> anyhow::Result<bool> {
+    dotenv().ok();
+    match env::var("GRAPH_BACKEND").as_deref() {
+        Ok("compressed") => Ok(true),
+        Ok("plain") | Err(_) => Ok(false),
+        Ok(other) => bail!("GRAPH_BACKEND must be plain or compressed, got {}", other),
+    }
+}
+
+/// DATA_DIR, the folder `build --out-dir` wrote to (default: data) or a bundle of it,
+/// also read by the cli tools
+pub fn data_dir_from_env() -> anyhow::Result<DataDir> {
+    dotenv().ok();
+    match env::var("DATA_DIR") {
+        Ok(dir) => DataDir::open(dir),
+        Err(_) => Ok(DataDir::default()),
+    }
+}
+
+/// DATASETS=enwiki=data/enwiki,simplewiki=data/simplewiki, or just the wiki in DATA_DIR
+/// as enwiki when it isn't set
+pub fn datasets_from_env() -> anyhow::Result<Vec<(String, DataDir)>> {
+    dotenv().ok();
+    let Ok(datasets) = env::var("DATASETS") else {
+        return Ok(vec![(DEFAULT_WIKI.to_string(), data_dir_from_env()?)]);
+    };
+
+    let mut parsed: Vec<(String, DataDir)> = Vec::new();
+    for dataset in datasets.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let Some((wiki, dir)) = dataset.split_once('=') else {
+            bail!("DATASETS entries look like wiki=dir, got {}", dataset);
+        };
+        if parsed.iter().any(|(seen, _)| seen == wiki) {
+            bail!("{} is in DATASETS twice", wiki);
+        }
+        parsed.push((wiki.to_string(), DataDir::open(dir)?));
+    }
+    if parsed.is_empty() {
+        bail!("DATASETS is set but empty");
+    }
+    Ok(parsed)
 }
 
 impl EnvironmentVariables {
@@ -58,6 +113,28 @@ impl EnvironmentVariables {
             Err(_) => false,
         };
 
+        let search_threads = env::var("SEARCH_THREADS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or_else(num_cpus::get);
+
+        let search_queue_limit = env::var("SEARCH_QUEUE_LIMIT")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(64);
+
+        let search_timeout_ms = env::var("SEARCH_TIMEOUT_MS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(10_000);
+
+        let search_max_nodes = env::var("SEARCH_MAX_NODES")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok());
+
+        let compressed_graph = compressed_graph_from_env()?;
+        let datasets = datasets_from_env()?;
+
         Ok(Self {
             jwt_secret,
             database_url,
@@ -67,6 +144,12 @@ impl EnvironmentVariables {
             frontend_origin,
             port,
             is_production,
+            search_threads,
+            search_queue_limit,
+            search_timeout_ms,
+            search_max_nodes,
+            compressed_graph,
+            datasets,
         })
     }
 }
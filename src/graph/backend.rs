@@ -0,0 +1,71 @@
+use std::iter::Copied;
+use std::slice;
+
+use crate::graph::compressed_csr::{CompressedCsrGraphMmap, VarintNeighbors};
+use crate::graph::csr_graph::{CsrGraphMmap, CsrGraphTrait};
+
+/// The graph the server searches, plain or compressed, picked at startup with GRAPH_BACKEND
+pub enum GraphBackend {
+    Plain(CsrGraphMmap),
+    Compressed(CompressedCsrGraphMmap),
+}
+
+pub enum BackendNeighbors<'a> {
+    Plain(Copied<slice::Iter<'a, u32>>),
+    Compressed(VarintNeighbors<'a>),
+}
+
+impl Iterator for BackendNeighbors<'_> {
+    type Item = u32;
+
+    #[inline]
+    fn next(&mut self) -> Option<u32> {
+        match self {
+            BackendNeighbors::Plain(neighbors) => neighbors.next(),
+            BackendNeighbors::Compressed(neighbors) => neighbors.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            BackendNeighbors::Plain(neighbors) => neighbors.size_hint(),
+            BackendNeighbors::Compressed(neighbors) => neighbors.size_hint(),
+        }
+    }
+}
+
+impl ExactSizeIterator for BackendNeighbors<'_> {}
+
+impl CsrGraphTrait for GraphBackend {
+    type Neighbors<'a> = BackendNeighbors<'a>;
+    fn neighbors(&self, dense_node: u32) -> Self::Neighbors<'_> {
+        match self {
+            GraphBackend::Plain(graph) => BackendNeighbors::Plain(graph.neighbors(dense_node)),
+            GraphBackend::Compressed(graph) => {
+                BackendNeighbors::Compressed(graph.neighbors(dense_node))
+            }
+        }
+    }
+    fn reverse_neighbors(&self, dense_node: u32) -> Self::Neighbors<'_> {
+        match self {
+            GraphBackend::Plain(graph) => {
+                BackendNeighbors::Plain(graph.reverse_neighbors(dense_node))
+            }
+            GraphBackend::Compressed(graph) => {
+                BackendNeighbors::Compressed(graph.reverse_neighbors(dense_node))
+            }
+        }
+    }
+    fn num_nodes(&self) -> usize {
+        match self {
+            GraphBackend::Plain(graph) => graph.num_nodes(),
+            GraphBackend::Compressed(graph) => graph.num_nodes(),
+        }
+    }
+    fn num_edges(&self) -> usize {
+        match self {
+            GraphBackend::Plain(graph) => graph.num_edges(),
+            GraphBackend::Compressed(graph) => graph.num_edges(),
+        }
+    }
+}
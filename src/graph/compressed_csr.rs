@@ -0,0 +1,105 @@
+use crate::graph::csr_graph::CsrGraphTrait;
+use crate::util;
+use crate::util::MmapView;
+
+/// CSR graph with every neighbour list stored as varints: the count, the first neighbour,
+/// then the gaps between neighbours. Lists are sorted so most gaps fit in one or two bytes.
+pub struct CompressedCsrGraphMmap {
+    pub offsets: MmapView, // Vec<u64>, byte offset of each node's list, num_nodes + 1
+    pub reverse_offsets: MmapView, // Vec<u64>
+    pub edges_mmap: MmapView, // varint bytes
+    pub reverse_edges_mmap: MmapView,
+    pub num_edges: usize,
+}
+
+fn varint_list<'a>(offsets: &MmapView, bytes: &'a [u8], dense_node: u32) -> VarintNeighbors<'a> {
+    let offsets: &[u64] = util::mmap_as_u64_slice(offsets);
+    let start = offsets[dense_node as usize] as usize;
+    let end = offsets[dense_node as usize + 1] as usize;
+    let mut bytes = &bytes[start..end];
+    let remaining = read_varint(&mut bytes);
+    VarintNeighbors {
+        bytes,
+        remaining,
+        prev: 0,
+    }
+}
+
+impl CsrGraphTrait for CompressedCsrGraphMmap {
+    type Neighbors<'a> = VarintNeighbors<'a>;
+    fn neighbors(&self, dense_node: u32) -> Self::Neighbors<'_> {
+        varint_list(&self.offsets, &self.edges_mmap, dense_node)
+    }
+    fn reverse_neighbors(&self, dense_node: u32) -> Self::Neighbors<'_> {
+        varint_list(&self.reverse_offsets, &self.reverse_edges_mmap, dense_node)
+    }
+    fn num_nodes(&self) -> usize {
+        util::mmap_as_u64_slice(&self.offsets)
+            .len()
+            .saturating_sub(1)
+    }
+    fn num_edges(&self) -> usize {
+        self.num_edges
+    }
+}
+
+pub struct VarintNeighbors<'a> {
+    bytes: &'a [u8],
+    remaining: u32,
+    prev: u32,
+}
+
+impl Iterator for VarintNeighbors<'_> {
+    type Item = u32;
+
+    #[inline]
+    fn next(&mut self) -> Option<u32> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // wrapping, so an unsorted list still decodes to what was encoded
+        self.prev = self.prev.wrapping_add(read_varint(&mut self.bytes));
+        Some(self.prev)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+impl ExactSizeIterator for VarintNeighbors<'_> {}
+
+/// Append one neighbour list in the compressed format
+pub fn encode_neighbors(neighbors: &[u32], out: &mut Vec<u8>) {
+    write_varint(neighbors.len() as u32, out);
+    let mut prev = 0u32;
+    for &neighbor in neighbors {
+        write_varint(neighbor.wrapping_sub(prev), out);
+        prev = neighbor;
+    }
+}
+
+// 7 bits per byte, high bit set on every byte but the last
+fn write_varint(mut value: u32, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[inline]
+fn read_varint(bytes: &mut &[u8]) -> u32 {
+    let mut value = 0u32;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[0];
+        *bytes = &bytes[1..];
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
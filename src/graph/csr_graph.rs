@@ -1,13 +1,36 @@
+use std::iter::Copied;
+use std::slice;
+
 use bitcode::{Decode, Encode};
-use memmap2::Mmap;
 use rustc_hash::FxHashMap;
 
 use crate::util;
+use crate::util::MmapView;
+
+// Sync so big frontiers can be expanded from several threads
+pub trait CsrGraphTrait: Sync {
+    /// Neighbours in sorted order. Graphs with plain neighbour arrays hand out a slice
+    /// iterator, compressed ones decode as they go.
+    type Neighbors<'a>: ExactSizeIterator<Item = u32>
+    where
+        Self: 'a;
+    fn neighbors(&self, dense_node: u32) -> Self::Neighbors<'_>;
+    fn reverse_neighbors(&self, dense_node: u32) -> Self::Neighbors<'_>;
+    fn num_nodes(&self) -> usize;
+    fn num_edges(&self) -> usize;
+
+    fn degree(&self, dense_node: u32) -> usize {
+        self.neighbors(dense_node).len()
+    }
+    fn reverse_degree(&self, dense_node: u32) -> usize {
+        self.reverse_neighbors(dense_node).len()
+    }
+}
 
-pub trait CsrGraphTrait {
+/// Graphs that store neighbours contiguously and can lend them as slices
+pub trait CsrSliceGraphTrait: CsrGraphTrait {
     fn get(&self, dense_node: u32) -> &[u32];
     fn get_reverse(&self, dense_node: u32) -> &[u32];
-    fn num_nodes(&self) -> usize;
 }
 
 #[derive(Encode, Decode)]
@@ -19,6 +42,22 @@ pub struct CsrGraph {
 }
 
 impl CsrGraphTrait for CsrGraph {
+    type Neighbors<'a> = Copied<slice::Iter<'a, u32>>;
+    fn neighbors(&self, dense_node: u32) -> Self::Neighbors<'_> {
+        self.get(dense_node).iter().copied()
+    }
+    fn reverse_neighbors(&self, dense_node: u32) -> Self::Neighbors<'_> {
+        self.get_reverse(dense_node).iter().copied()
+    }
+    fn num_nodes(&self) -> usize {
+        self.offsets.len() - 1
+    }
+    fn num_edges(&self) -> usize {
+        self.edges.len()
+    }
+}
+
+impl CsrSliceGraphTrait for CsrGraph {
     fn get(&self, dense_node: u32) -> &[u32] {
         let start = self.offsets[dense_node as usize] as usize;
         let end = self.offsets[dense_node as usize + 1] as usize;
@@ -29,19 +68,33 @@ impl CsrGraphTrait for CsrGraph {
         let end = self.reverse_offsets[dense_node as usize + 1] as usize;
         &self.reverse_edges[start..end]
     }
-    fn num_nodes(&self) -> usize {
-        self.offsets.len() - 1
-    }
 }
 
 pub struct CsrGraphMmap {
-    pub offsets: Mmap,
-    pub reverse_offsets: Mmap,
-    pub edges_mmap: Mmap,
-    pub reverse_edges_mmap: Mmap,
+    pub offsets: MmapView,
+    pub reverse_offsets: MmapView,
+    pub edges_mmap: MmapView,
+    pub reverse_edges_mmap: MmapView,
 }
 
 impl CsrGraphTrait for CsrGraphMmap {
+    type Neighbors<'a> = Copied<slice::Iter<'a, u32>>;
+    fn neighbors(&self, dense_node: u32) -> Self::Neighbors<'_> {
+        self.get(dense_node).iter().copied()
+    }
+    fn reverse_neighbors(&self, dense_node: u32) -> Self::Neighbors<'_> {
+        self.get_reverse(dense_node).iter().copied()
+    }
+    fn num_nodes(&self) -> usize {
+        let offsets: &[u32] = util::mmap_as_u32_slice(&self.offsets);
+        offsets.len().saturating_sub(1)
+    }
+    fn num_edges(&self) -> usize {
+        util::mmap_as_u32_slice(&self.edges_mmap).len()
+    }
+}
+
+impl CsrSliceGraphTrait for CsrGraphMmap {
     fn get(&self, dense_node: u32) -> &[u32] {
         let offsets: &[u32] = util::mmap_as_u32_slice(&self.offsets);
         let edges: &[u32] = util::mmap_as_u32_slice(&self.edges_mmap);
@@ -56,10 +109,6 @@ impl CsrGraphTrait for CsrGraphMmap {
         let end = reverse_offsets[dense_node as usize + 1] as usize;
         &revedges[start..end]
     }
-    fn num_nodes(&self) -> usize {
-        let offsets: &[u32] = util::mmap_as_u32_slice(&self.offsets);
-        offsets.len().saturating_sub(1)
-    }
 }
 
 pub fn build_csr_with_adjacency_list(
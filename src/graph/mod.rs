@@ -1,5 +1,11 @@
+pub mod backend;
+pub mod compressed_csr;
 pub mod csr_graph;
+pub mod scc;
 
+pub use backend::*;
+pub use compressed_csr::*;
 pub use csr_graph::*;
+pub use scc::*;
 
 // the adjacency list is built in pagelinks_parser
@@ -0,0 +1,125 @@
+use crate::graph::csr_graph::{CsrGraph, CsrGraphTrait};
+
+/// Component id of every node, from Kosaraju's algorithm with explicit stacks
+/// (the graph is far too deep for recursion). Ids come out in topological order
+/// of the condensation: every edge between components goes to a higher id.
+pub fn strongly_connected_components<G: CsrGraphTrait>(graph: &G) -> (Vec<u32>, usize) {
+    let num_nodes = graph.num_nodes();
+
+    // pass 1: nodes in order of dfs finish time
+    let mut visited = vec![false; num_nodes];
+    let mut finish_order: Vec<u32> = Vec::with_capacity(num_nodes);
+    // (node, the edges not looked at yet)
+    let mut stack: Vec<(u32, G::Neighbors<'_>)> = Vec::new();
+    for root in 0..num_nodes as u32 {
+        if visited[root as usize] {
+            continue;
+        }
+        visited[root as usize] = true;
+        stack.push((root, graph.neighbors(root)));
+        while let Some((node, neighbors)) = stack.last_mut() {
+            if let Some(neighbor) = neighbors.next() {
+                if !visited[neighbor as usize] {
+                    visited[neighbor as usize] = true;
+                    stack.push((neighbor, graph.neighbors(neighbor)));
+                }
+            } else {
+                finish_order.push(*node);
+                stack.pop();
+            }
+        }
+    }
+    drop(visited);
+
+    // pass 2: latest finish first, everything that reaches it backwards is its component
+    let mut component_ids = vec![u32::MAX; num_nodes];
+    let mut num_components: u32 = 0;
+    let mut component_stack: Vec<u32> = Vec::new();
+    for &root in finish_order.iter().rev() {
+        if component_ids[root as usize] != u32::MAX {
+            continue;
+        }
+        component_ids[root as usize] = num_components;
+        component_stack.push(root);
+        while let Some(node) = component_stack.pop() {
+            for neighbor in graph.reverse_neighbors(node) {
+                if component_ids[neighbor as usize] == u32::MAX {
+                    component_ids[neighbor as usize] = num_components;
+                    component_stack.push(neighbor);
+                }
+            }
+        }
+        num_components += 1;
+    }
+
+    (component_ids, num_components as usize)
+}
+
+/// One node per component, an edge wherever the graph has at least one edge between two components
+pub fn condensation<G: CsrGraphTrait>(
+    graph: &G,
+    component_ids: &[u32],
+    num_components: usize,
+) -> CsrGraph {
+    // nodes grouped by component (counting sort)
+    let mut members_offsets = vec![0u32; num_components + 1];
+    for &component in component_ids {
+        members_offsets[component as usize + 1] += 1;
+    }
+    for i in 0..num_components {
+        members_offsets[i + 1] += members_offsets[i];
+    }
+    let mut fill = members_offsets.clone();
+    let mut members = vec![0u32; component_ids.len()];
+    for (node, &component) in component_ids.iter().enumerate() {
+        members[fill[component as usize] as usize] = node as u32;
+        fill[component as usize] += 1;
+    }
+    drop(fill);
+
+    let mut offsets = Vec::with_capacity(num_components + 1);
+    let mut edges = Vec::new();
+    offsets.push(0);
+    let mut targets: Vec<u32> = Vec::new();
+    for component in 0..num_components {
+        let start = members_offsets[component] as usize;
+        let end = members_offsets[component + 1] as usize;
+        targets.clear();
+        for &node in &members[start..end] {
+            targets.extend(
+                graph
+                    .neighbors(node)
+                    .map(|neighbor| component_ids[neighbor as usize])
+                    .filter(|&target| target != component as u32),
+            );
+        }
+        targets.sort_unstable();
+        targets.dedup();
+        edges.extend_from_slice(&targets);
+        offsets.push(edges.len() as u32);
+    }
+
+    // reverse edges, also sorted since sources are visited in order
+    let mut reverse_offsets = vec![0u32; num_components + 1];
+    for &target in &edges {
+        reverse_offsets[target as usize + 1] += 1;
+    }
+    for i in 0..num_components {
+        reverse_offsets[i + 1] += reverse_offsets[i];
+    }
+    let mut fill = reverse_offsets.clone();
+    let mut reverse_edges = vec![0u32; edges.len()];
+    for source in 0..num_components {
+        for &target in &edges[offsets[source] as usize..offsets[source + 1] as usize] {
+            reverse_edges[fill[target as usize] as usize] = source as u32;
+            fill[target as usize] += 1;
+        }
+    }
+
+    CsrGraph {
+        offsets,
+        edges,
+        reverse_offsets,
+        reverse_edges,
+    }
+}
@@ -0,0 +1,299 @@
+// src/k_shortest.rs
+// k shortest loopless paths with yen's algorithm, every spur search is a bidirectional bfs
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use rustc_hash::FxHashSet;
+
+use crate::graph::CsrGraphTrait;
+use crate::mmap_structs::*;
+use crate::paths::DataDir;
+use crate::search::{
+    resolve_redirects, LimitHit, SearchLimits, SearchOutcome, Searcher, EXCLUDED_DEPTH,
+};
+use crate::search_context::{SearchContext, StampedDepths};
+
+/// Up to k loopless paths, shortest first. Paths are dense ids with redirects not resolved yet.
+pub struct KShortestPaths {
+    pub paths: Vec<Vec<u32>>,
+    // set when a limit stopped the search before k paths were found, there might be more
+    pub stopped_by: Option<LimitHit>,
+}
+
+/// Yen's algorithm: every path after the first is the best detour from a node (the spur)
+/// of the previous path, avoiding the nodes before the spur and the edges out of the spur
+/// that already found paths with the same prefix take. `excluded` nodes are never used.
+pub fn k_shortest_paths<G>(
+    searcher: &mut Searcher<G>,
+    start: u32,
+    goal: u32,
+    k: usize,
+    excluded: &[u32],
+) -> SearchOutcome<KShortestPaths>
+where
+    G: CsrGraphTrait,
+{
+    let first = match shortest_path(searcher, start, goal, excluded, &[]) {
+        SearchOutcome::Found(path) => path,
+        SearchOutcome::NoPath => return SearchOutcome::NoPath,
+        SearchOutcome::Exhausted(limit) => return SearchOutcome::Exhausted(limit),
+    };
+
+    let mut found: Vec<Vec<u32>> = vec![first.clone()];
+    let mut seen: FxHashSet<Vec<u32>> = FxHashSet::default();
+    seen.insert(first);
+    // candidates, shortest first (ties by the path itself so the order is deterministic)
+    let mut candidates: BinaryHeap<Reverse<(usize, Vec<u32>)>> = BinaryHeap::new();
+    let mut spur_excluded: Vec<u32> = Vec::new();
+
+    while found.len() < k {
+        let prev = found.last().unwrap().clone();
+        for i in 0..prev.len() - 1 {
+            let (spur, root) = (prev[i], &prev[..=i]);
+
+            // next hops already taken from this root
+            let blocked: Vec<u32> = found
+                .iter()
+                .filter(|path| path.len() > i + 1 && path[..=i] == *root)
+                .map(|path| path[i + 1])
+                .collect();
+            // the root can't be revisited, that would make a loop
+            spur_excluded.clear();
+            spur_excluded.extend_from_slice(excluded);
+            spur_excluded.extend_from_slice(&root[..i]);
+
+            match shortest_path(searcher, spur, goal, &spur_excluded, &blocked) {
+                SearchOutcome::Found(spur_path) => {
+                    let mut path = root[..i].to_vec();
+                    path.extend(spur_path);
+                    if seen.insert(path.clone()) {
+                        candidates.push(Reverse((path.len(), path)));
+                    }
+                }
+                SearchOutcome::NoPath => {}
+                SearchOutcome::Exhausted(limit) => {
+                    return SearchOutcome::Found(KShortestPaths {
+                        paths: found,
+                        stopped_by: Some(limit),
+                    })
+                }
+            }
+        }
+
+        match candidates.pop() {
+            Some(Reverse((_, path))) => found.push(path),
+            // fewer than k loopless paths exist
+            None => break,
+        }
+    }
+
+    SearchOutcome::Found(KShortestPaths {
+        paths: found,
+        stopped_by: None,
+    })
+}
+
+/// One shortest path that avoids the `excluded` nodes and doesn't take the edges
+/// start -> `blocked_first_hops`. Returns as soon as the two sides meet.
+fn shortest_path<G>(
+    searcher: &mut Searcher<G>,
+    start: u32,
+    goal: u32,
+    excluded: &[u32],
+    blocked_first_hops: &[u32],
+) -> SearchOutcome<Vec<u32>>
+where
+    G: CsrGraphTrait,
+{
+    if start == goal {
+        return SearchOutcome::Found(vec![start]);
+    }
+    let (graph, limits) = (searcher.graph, searcher.limits);
+    let node_count = &mut searcher.node_count;
+    let ctx = &mut *searcher.ctx;
+
+    ctx.reset();
+    let SearchContext {
+        depth_fwd: visited_depth_fwd,
+        depth_bwd: visited_depth_bwd,
+        frontier_fwd,
+        frontier_bwd,
+        next_frontier,
+        ..
+    } = ctx;
+
+    for &node in excluded {
+        visited_depth_fwd.set(node, EXCLUDED_DEPTH);
+        visited_depth_bwd.set(node, EXCLUDED_DEPTH);
+    }
+    visited_depth_fwd.set(start, 0);
+    visited_depth_bwd.set(goal, 0);
+    frontier_fwd.push(start);
+    frontier_bwd.push(goal);
+
+    let is_blocked = |from: u32, to: u32| from == start && blocked_first_hops.contains(&to);
+
+    let mut depth_fwd: u8 = 0;
+    let mut depth_bwd: u8 = 0;
+
+    while !frontier_fwd.is_empty() && !frontier_bwd.is_empty() {
+        if depth_fwd + depth_bwd >= limits.max_depth {
+            return SearchOutcome::Exhausted(LimitHit::MaxDepth);
+        }
+
+        let (frontier, visited_depth_this, visited_depth_other, depth, backwards) =
+            if frontier_fwd.len() <= frontier_bwd.len() {
+                (
+                    &mut *frontier_fwd,
+                    &mut *visited_depth_fwd,
+                    &*visited_depth_bwd,
+                    &mut depth_fwd,
+                    false,
+                )
+            } else {
+                (
+                    &mut *frontier_bwd,
+                    &mut *visited_depth_bwd,
+                    &*visited_depth_fwd,
+                    &mut depth_bwd,
+                    true,
+                )
+            };
+        *depth += 1;
+        let next_depth = *depth;
+
+        next_frontier.clear();
+        let mut meet = None;
+        'expand: for &node in frontier.iter() {
+            *node_count += 1;
+            if let Err(limit) = limits.check(*node_count) {
+                return SearchOutcome::Exhausted(limit);
+            }
+
+            let neighbors = if backwards {
+                graph.reverse_neighbors(node)
+            } else {
+                graph.neighbors(node)
+            };
+            for neighbor in neighbors {
+                let blocked = if backwards {
+                    is_blocked(neighbor, node)
+                } else {
+                    is_blocked(node, neighbor)
+                };
+                if blocked || !visited_depth_this.insert(neighbor, next_depth) {
+                    continue;
+                }
+                if visited_depth_other.contains(neighbor) {
+                    meet = Some(neighbor);
+                    break 'expand;
+                }
+                next_frontier.push(neighbor);
+            }
+        }
+
+        if let Some(meet) = meet {
+            // start -> meet, then meet -> goal
+            let mut path = walk_to_root(graph, visited_depth_fwd, meet, false, &is_blocked);
+            path.reverse();
+            path.extend(
+                walk_to_root(graph, visited_depth_bwd, meet, true, &is_blocked)
+                    .into_iter()
+                    .skip(1),
+            );
+            return SearchOutcome::Found(path);
+        }
+        std::mem::swap(frontier, next_frontier);
+    }
+
+    SearchOutcome::NoPath
+}
+
+/// Follow depths down from `node` to the side's root (depth 0), one node per level
+fn walk_to_root<G>(
+    graph: &G,
+    visited_depth: &StampedDepths,
+    mut node: u32,
+    backwards: bool,
+    is_blocked: &impl Fn(u32, u32) -> bool,
+) -> Vec<u32>
+where
+    G: CsrGraphTrait,
+{
+    let mut path = vec![node];
+    let mut depth = visited_depth.get(node).unwrap();
+    while depth > 0 {
+        // the previous node links to this one, so look at the edges going the other way
+        let mut candidates = if backwards {
+            graph.neighbors(node)
+        } else {
+            graph.reverse_neighbors(node)
+        };
+        node = candidates
+            .find(|&prev| {
+                let blocked = if backwards {
+                    is_blocked(node, prev)
+                } else {
+                    is_blocked(prev, node)
+                };
+                !blocked && visited_depth.get(prev) == Some(depth - 1)
+            })
+            .unwrap();
+        depth -= 1;
+        path.push(node);
+    }
+    path
+}
+
+/// Print the k shortest loopless paths between two titles
+pub fn run_k_shortest_cli(
+    data: &DataDir,
+    start_title: &str,
+    goal_title: &str,
+    k: usize,
+) -> anyhow::Result<()> {
+    let csr_graph = load_csr_graph_mmap(data)?;
+    let title_to_dense_id = load_title_to_dense_id_mmap(data)?;
+    let dense_id_to_title = load_dense_id_to_title_mmap(data)?;
+    let redirect_targets_dense = load_redirect_targets_dense_mmap(data)?;
+    let redirects_passed = load_redirects_passed_mmap(data)?;
+
+    let resolve = |title: &str| -> anyhow::Result<u32> {
+        let dense_id = title_to_dense_id
+            .get(title)
+            .ok_or_else(|| anyhow::anyhow!("title '{}' not found", title))?;
+        Ok(match redirect_targets_dense.get(dense_id) {
+            u32::MAX => dense_id,
+            redirect => redirect,
+        })
+    };
+    let (start, goal) = (resolve(start_title)?, resolve(goal_title)?);
+
+    let mut ctx = SearchContext::new(csr_graph.num_nodes());
+    let limits = SearchLimits::with_max_depth(50);
+    let mut searcher = Searcher::new(&csr_graph, &mut ctx, &limits);
+    let outcome = k_shortest_paths(&mut searcher, start, goal, k, &[]);
+
+    match outcome {
+        SearchOutcome::Found(k_paths) => {
+            for (i, mut path) in k_paths.paths.into_iter().enumerate() {
+                resolve_redirects(&mut path, &redirects_passed);
+                let titles: Vec<&str> = path.iter().map(|&id| dense_id_to_title.get(id)).collect();
+                println!(
+                    "{:>3} ({} nodes): {}",
+                    i + 1,
+                    path.len(),
+                    titles.join(" -> ")
+                );
+            }
+            if let Some(limit) = k_paths.stopped_by {
+                println!("stopped early by {:?}", limit);
+            }
+        }
+        SearchOutcome::NoPath => println!("No path found."),
+        SearchOutcome::Exhausted(limit) => println!("Search stopped by {:?}.", limit),
+    }
+    println!("{} nodes expanded", searcher.node_count);
+    Ok(())
+}
@@ -4,22 +4,46 @@ use deadpool_redis::Pool as RedisPool;
 use redis::AsyncCommands;
 use sqlx::{Row, SqlitePool};
 
+/// Redis sorted set of one wiki's leaderboard, members are "start_id|end_id"
+pub fn leaderboard_key(wiki: &str, leaderboard_type: &str) -> String {
+    format!("leaderboard:{}:{}", wiki, leaderboard_type)
+}
+
+/// Field of the leaderboard:username hash holding who found a path
+pub fn username_field(path_value: &str, wiki: &str, leaderboard_type: &str) -> String {
+    format!("{}|{}|{}", path_value, wiki, leaderboard_type)
+}
+
+/// The search a path on a leaderboard came from
+pub struct LeaderboardSearch<'a> {
+    pub wiki: &'a str,
+    pub start_id: u32,
+    pub end_id: u32,
+    pub search_id: &'a str,
+    pub user_id: &'a str,
+}
+
 pub async fn try_add_to_leaderboard(
     sqlite_pool: &sqlx::SqlitePool,
     redis_pool: &deadpool_redis::Pool,
+    search: &LeaderboardSearch<'_>,
     leaderboard_type: &str,
-    start_id: u32,
-    end_id: u32,
     score: u32,
-    search_id: &str,
-    user_id: &str,
     top_n: u32,
 ) -> Option<u32> {
+    let LeaderboardSearch {
+        wiki,
+        start_id,
+        end_id,
+        search_id,
+        user_id,
+    } = *search;
     let claim = sqlx::query!(
         r#"
-        INSERT OR IGNORE INTO claimed_paths (start_id, end_id, leaderboard, search_id)
-        VALUES (?1, ?2, ?3, ?4)
+        INSERT OR IGNORE INTO claimed_paths (wiki, start_id, end_id, leaderboard, search_id)
+        VALUES (?1, ?2, ?3, ?4, ?5)
         "#,
+        wiki,
         start_id,
         end_id,
         leaderboard_type,
@@ -33,7 +57,7 @@ pub async fn try_add_to_leaderboard(
         return None;
     }
 
-    let leaderboard_key = &format!("leaderboard:{}", leaderboard_type);
+    let leaderboard_key = &leaderboard_key(wiki, leaderboard_type);
 
     let mut conn = redis_pool.get().await.unwrap();
 
@@ -60,7 +84,7 @@ pub async fn try_add_to_leaderboard(
         let _: () = conn
             .hset(
                 &hash_key,
-                format!("{}|{}|{}", start_id, end_id, leaderboard_type),
+                username_field(&path_value, wiki, leaderboard_type),
                 username,
             )
             .await
@@ -94,7 +118,7 @@ pub async fn try_add_to_leaderboard(
                 let _: () = conn
                     .hset(
                         &hash_key,
-                        format!("{}|{}|{}", start_id, end_id, leaderboard_type),
+                        username_field(&path_value, wiki, leaderboard_type),
                         username,
                     )
                     .await
@@ -114,6 +138,7 @@ pub async fn try_add_to_leaderboard(
 pub async fn populate_leaderboard(
     sqlite_pool: &sqlx::SqlitePool,
     redis_pool: &deadpool_redis::Pool,
+    wiki: &str,
     leaderboard: &str,   // "most or longest"
     metric_column: &str, // e.g., "path_length" or "num_paths"
     top_n: u32,
@@ -121,7 +146,7 @@ pub async fn populate_leaderboard(
     let mut redis_conn = redis_pool.get().await?;
 
     // 1️⃣ Clear the leaderboard
-    let leaderboard_key = format!("leaderboard:{}", leaderboard);
+    let leaderboard_key = leaderboard_key(wiki, leaderboard);
     let username_hash_key = "leaderboard:username";
     let _: () = redis_conn.del(&leaderboard_key).await?;
     // let _: () = redis_conn.del(&username_hash_key).await?;
@@ -132,7 +157,7 @@ pub async fn populate_leaderboard(
         FROM claimed_paths cp
         JOIN searches s ON s.id = cp.search_id
         JOIN users u ON u.id = s.user_id
-        WHERE cp.leaderboard = ?
+        WHERE cp.wiki = ? AND cp.leaderboard = ?
         ORDER BY s.{metric} DESC
         LIMIT ?;
         ",
@@ -140,13 +165,13 @@ pub async fn populate_leaderboard(
     );
 
     let rows = sqlx::query(&query)
+        .bind(wiki)
         .bind(leaderboard)
         .bind(top_n)
         .fetch_all(sqlite_pool)
         .await?;
 
     // 3️⃣ Insert into Redis leaderboard
-    let leaderboard_key = format!("leaderboard:{}", leaderboard);
     for row in rows {
         let start_id: u32 = row.get("start_id");
         let end_id: u32 = row.get("end_id");
@@ -155,12 +180,14 @@ pub async fn populate_leaderboard(
 
         let path_value = format!("{}|{}", start_id, end_id);
 
-        let _: () = redis_conn.zadd(&leaderboard_key, path_value, score).await?;
+        let _: () = redis_conn
+            .zadd(&leaderboard_key, &path_value, score)
+            .await?;
         // username hash
         let _: () = redis_conn
             .hset(
                 &username_hash_key,
-                format!("{}|{}|{}", start_id, end_id, leaderboard),
+                username_field(&path_value, wiki, leaderboard),
                 username,
             )
             .await?;
@@ -8,14 +8,23 @@ mod util;
 
 // Your other existing modules
 mod builders;
+mod bundle;
 mod graph;
+mod k_shortest;
 mod mmap_structs;
 mod parsers;
+mod paths;
+mod puzzle;
 mod routes;
 mod search; // This is important!
+mod search_context;
+mod search_executor;
+mod simulate;
 
 use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::atomic::AtomicUsize;
 use std::time::Duration;
 use std::time::Instant;
@@ -25,10 +34,15 @@ use crate::config::EnvironmentVariables;
 use crate::graph::CsrGraphTrait;
 use crate::leaderboard::populate_leaderboard;
 use crate::mmap_structs::*;
+use crate::paths::{DataDir, Dumps};
+use crate::puzzle::Difficulty;
 use crate::routes::create_router;
-use crate::state::AppState;
+use crate::search_context::SearchContextPool;
+use crate::search_executor::SearchExecutor;
+use crate::simulate::Strategy;
+use crate::state::{AppState, Dataset};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use deadpool_redis::Config as RedisConfig;
 
 // todo:
@@ -42,7 +56,7 @@ use deadpool_redis::Config as RedisConfig;
 // replaced bincode serialization with rkyv see if its faster
 
 // reordering for locality (for csr):
-//   for csr RCM (Reverse Cuthill-McKee), putting similar pages together
+//   bfs, RCM (Reverse Cuthill-McKee) and degree order done, see --reorder
 //   or reordering with community detection (louvain, Label Propagation, Girvan–Newman, Infomap, etc)
 //   or graph partitioning (for parallel processing or community detection?) (METIS, KaHIP)
 
@@ -52,18 +66,160 @@ use deadpool_redis::Config as RedisConfig;
 #[command(name = "wikirace")]
 #[command(about = "Find shortest paths between Wikipedia pages", long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Rebuild the memory-mapped files
     #[arg(long)]
     rebuild: bool,
 
+    /// Renumber pages for cache locality while rebuilding
+    #[arg(long, value_enum, requires = "rebuild")]
+    reorder: Option<ReorderStrategy>,
+
+    /// Rebuild this stage and everything built from it, even if it's up to date
+    #[arg(long, value_enum, requires = "rebuild")]
+    stage: Option<Stage>,
+
     #[arg(long)]
     benchmark: bool,
 
+    /// Time the old hashmap search against the pooled search on this many random pairs
+    #[arg(long)]
+    benchmark_search: Option<usize>,
+
+    /// Print the k shortest loopless paths between two titles
+    #[arg(long, num_args = 2, value_names = ["START", "END"])]
+    k_shortest: Option<Vec<String>>,
+
+    /// Number of paths for --k-shortest
+    #[arg(long, default_value_t = 10)]
+    k: usize,
+
+    /// Print how many pages are at each distance from a title
+    #[arg(long, value_name = "TITLE")]
+    distances: Option<String>,
+
+    /// With --distances, count distances to the title instead (following links backwards)
+    #[arg(long)]
+    reverse: bool,
+
+    /// With --distances, print the distance and a shortest path to every title in this file instead
+    #[arg(long, value_name = "FILE", requires = "distances")]
+    targets: Option<String>,
+
+    /// Compute strongly connected components and the condensation dag
+    #[arg(long)]
+    build_scc: bool,
+
+    /// Print strongly connected component stats
+    #[arg(long)]
+    scc_stats: bool,
+
+    /// Pick this many landmarks and save the distances from and to them
+    #[arg(long, value_name = "COUNT")]
+    build_landmarks: Option<usize>,
+
+    /// Save a delta + varint compressed copy of the csr graph
+    #[arg(long)]
+    build_compressed_csr: bool,
+
+    /// Compare the compressed graph with the plain one, timing full bfs from this many pages
+    #[arg(long, value_name = "SOURCES")]
+    benchmark_compressed: Option<usize>,
+
+    /// Read every mmap file in the data dir and check its checksum
+    #[arg(long)]
+    verify_data: bool,
+
+    /// Play simulated games between this many random pairs and compare them to the shortest paths
+    #[arg(long, value_name = "PAIRS")]
+    simulate: Option<usize>,
+
+    /// Games per pair and strategy for --simulate
+    #[arg(long, default_value_t = 10)]
+    games: usize,
+
+    /// Clicks before a simulated game gives up
+    #[arg(long, default_value_t = 100)]
+    max_clicks: u32,
+
+    /// Strategies for --simulate, comma separated (default: all of them)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    strategy: Vec<Strategy>,
+
+    /// Print this many start/goal pairs for puzzles, as json
+    #[arg(long, value_name = "COUNT")]
+    puzzles: Option<usize>,
+
+    /// Band of pairs for --puzzles
+    #[arg(long, value_enum, default_value_t = Difficulty::Medium)]
+    difficulty: Difficulty,
+
+    /// With --puzzles, shortest path length instead of the difficulty's (one value or a range)
+    #[arg(long, num_args = 1..=2, value_names = ["MIN", "MAX"])]
+    distance: Option<Vec<u8>>,
+
+    /// With --puzzles, most shortest paths a pair can have
+    #[arg(long)]
+    max_paths: Option<u64>,
+
+    /// Seed for --puzzles, the same seed gives the same pairs
+    #[arg(long, conflicts_with = "daily")]
+    seed: Option<u64>,
+
+    /// With --puzzles, start from today's daily puzzle seed
+    #[arg(long)]
+    daily: bool,
+
+    /// Print --puzzles as csv instead
+    #[arg(long)]
+    csv: bool,
+
     /// Port for the API server
     #[arg(short, long, default_value_t = 3000)]
     port: u16,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Build every data file for one wiki from its sql dumps
+    Build(BuildArgs),
+    /// Pack a data dir into one bundle file, or unpack a bundle into a data dir
+    Convert(ConvertArgs),
+}
+
+#[derive(clap::Args)]
+struct BuildArgs {
+    /// Folder with the {wiki}-latest-*.sql.gz dumps
+    #[arg(long, default_value = paths::DEFAULT_DUMPS_DIR)]
+    dumps_dir: PathBuf,
+
+    /// Which wiki the dumps are from, like enwiki, dewiki or simplewiki
+    #[arg(long, default_value = paths::DEFAULT_WIKI)]
+    wiki: String,
+
+    /// Where the built files go (default: DATA_DIR, or data)
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
+
+    /// Renumber pages for cache locality
+    #[arg(long, value_enum)]
+    reorder: Option<ReorderStrategy>,
+
+    /// Rebuild this stage and everything built from it, even if it's up to date
+    #[arg(long, value_enum)]
+    stage: Option<Stage>,
+}
+
+#[derive(clap::Args)]
+struct ConvertArgs {
+    /// A data dir to pack, or a bundle to unpack
+    from: PathBuf,
+    /// The bundle file or data dir to write
+    to: PathBuf,
+}
+
 use axum::http::Method;
 use reqwest::header::ACCEPT;
 use reqwest::header::CONTENT_TYPE;
@@ -119,9 +275,9 @@ pub fn find_sources_sinks<G: CsrGraphTrait>(g: &G, limit: usize) -> (Vec<u32>, V
     let mut sinks = Vec::with_capacity(limit.min(1024));
 
     for u in 0..n {
-        // Using lengths avoids touching edges beyond computing slice bounds.
-        let outdeg = g.get(u).len();
-        let indeg = g.get_reverse(u).len();
+        // degrees don't touch the edges on the plain graph
+        let outdeg = g.degree(u);
+        let indeg = g.reverse_degree(u);
 
         if indeg == 0 && sources.len() < limit {
             sources.push(u);
@@ -159,6 +315,29 @@ fn save_titles_to_file(
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
     let now = Instant::now();
+    // where the cli tools read the built files from, the server reads it again from its config
+    let data = config::data_dir_from_env()?;
+
+    if let Some(Command::Build(build)) = &args.command {
+        let dumps = Dumps {
+            dir: build.dumps_dir.clone(),
+            wiki: build.wiki.clone(),
+        };
+        let out_dir = match &build.out_dir {
+            Some(dir) => DataDir::open(dir)?,
+            None => data,
+        };
+        run_build(&dumps, &out_dir, build.reorder, build.stage)?;
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if let Some(Command::Convert(convert)) = &args.command {
+        bundle::run_convert(&convert.from, &convert.to)?;
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
 
     if args.benchmark {
         // let dense_id_to_title = Arc::new(load_dense_id_to_title_mmap()?);
@@ -172,38 +351,112 @@ async fn main() -> anyhow::Result<()> {
 
         let num_threads = num_cpus::get(); // logical cores
         println!("running on {} threads", num_threads);
-        let filename = run_bfs_worker(num_threads, 10000, 5 * num_threads * 10000)?;
+        let filename = run_bfs_worker(&data, num_threads, 10000, 5 * num_threads * 10000)?;
         println!("BFS finished. Results saved to {}", filename);
         println!("Elapsed: {:.2?}", now.elapsed());
 
         return Ok(());
     }
+
+    if let Some(num_pairs) = args.benchmark_search {
+        let csr_graph = load_csr_graph_mmap(&data)?;
+        let redirect_targets_dense = load_redirect_targets_dense_mmap(&data)?;
+        search::benchmark_random_bfs(&csr_graph, &redirect_targets_dense, num_pairs, 50);
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if let Some(num_pairs) = args.simulate {
+        let strategies = if args.strategy.is_empty() {
+            vec![Strategy::Random, Strategy::Greedy, Strategy::Tokens]
+        } else {
+            args.strategy.clone()
+        };
+        simulate::run_simulate_cli(&data, num_pairs, args.games, args.max_clicks, &strategies)?;
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if let Some(count) = args.puzzles {
+        let mut band = args.difficulty.band();
+        if let Some(distance) = &args.distance {
+            band.min_distance = distance[0];
+            band.max_distance = *distance.last().unwrap();
+        }
+        if let Some(max_paths) = args.max_paths {
+            band.max_paths = max_paths;
+        }
+        let seed = if args.daily {
+            let today = chrono::Utc::now().date_naive();
+            Some(puzzle::daily_seed(today, args.difficulty))
+        } else {
+            args.seed
+        };
+        puzzle::run_puzzles_cli(&data, count, &band, seed, args.csv)?;
+        eprintln!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if let Some(titles) = &args.k_shortest {
+        k_shortest::run_k_shortest_cli(&data, &titles[0], &titles[1], args.k)?;
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if let Some(title) = &args.distances {
+        match &args.targets {
+            Some(targets_path) => {
+                search::print_target_distances(&data, title, targets_path, args.reverse)?
+            }
+            None => search::print_distance_histogram(&data, title, args.reverse)?,
+        }
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if args.build_scc {
+        build_and_save_scc_mmap(&data)?;
+    }
+    if args.build_scc || args.scc_stats {
+        print_scc_stats(&data)?;
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if let Some(num_landmarks) = args.build_landmarks {
+        build_and_save_landmarks_mmap(&data, num_landmarks)?;
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if args.build_compressed_csr {
+        build_and_save_compressed_csr_mmap(&data)?;
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if let Some(num_sources) = args.benchmark_compressed {
+        print_compressed_csr_report(&data, num_sources)?;
+        println!("Elapsed: {:.2?}", now.elapsed());
+
+        return Ok(());
+    }
+    if args.verify_data {
+        let failed = util::verify_checksums(&data)?;
+        println!("Elapsed: {:.2?}", now.elapsed());
+        if failed > 0 {
+            anyhow::bail!("{} files failed the check, rebuild them", failed);
+        }
+
+        return Ok(());
+    }
     // ------------------------------------------------------------------------
 
     let _guard = init_tracing();
     tracing::error!("this is a test error");
 
     if args.rebuild {
-        println!("Rebuilding structures...");
-
-        // build and save normal structures
-        build_and_save_page_maps_dense()?;
-        // ↓
-        build_and_save_linktargets_dense()?;
-        build_and_save_redirect_targets_dense()?;
-        // ↓
-        build_and_save_pagelinks_adj_list()?;
-        // ↓
-        build_and_save_pagelinks_csr()?;
-
-        // build and save mmap structures
-        build_and_save_title_to_dense_id_mmap()?;
-        build_and_save_dense_id_to_title_mmap()?;
-        build_and_save_orig_to_dense_id_mmap()?;
-        build_and_save_dense_id_to_orig_mmap()?;
-        build_and_save_pagelinks_csr()?;
-        build_and_save_redirects_passed_mmap()?;
-        build_and_save_redirect_targets_dense_mmap()?;
+        run_build(&Dumps::default(), &data, args.reorder, args.stage)?;
     }
 
     // load normal structures
@@ -243,36 +496,47 @@ async fn main() -> anyhow::Result<()> {
     // save_titles_to_file("sinks.txt", &sinks, &dense_to_title)?;
 
     // return Ok(());
+    let mut datasets = BTreeMap::new();
+    for (wiki, data) in &env.datasets {
+        tracing::info!("loading {} from {}", wiki, data.path().display());
+        let dataset = Dataset::load(wiki, data, env.compressed_graph)?;
+        datasets.insert(wiki.clone(), Arc::new(dataset));
+    }
+    let search_executor = Arc::new(SearchExecutor::new(
+        env.search_threads,
+        env.search_queue_limit,
+    ));
     let state = AppState {
-        title_to_dense_id: Arc::new(load_title_to_dense_id_mmap()?),
-        dense_id_to_title: Arc::new(load_dense_id_to_title_mmap()?),
-        dense_id_to_orig: Arc::new(load_dense_id_to_orig_mmap()?),
-        orig_to_dense_id: Arc::new(load_orig_to_dense_id_mmap()?),
-        redirects_passed: Arc::new(load_redirects_passed_mmap()?),
-        redirect_targets_dense: Arc::new(load_redirect_targets_dense_mmap()?),
-        csr_graph: Arc::new(load_csr_graph_mmap()?),
+        datasets,
+        default_wiki: env.datasets[0].0.clone(),
+        search_executor,
         redis_pool: redis_pool,
         sqlite_pool: sqlite_pool,
         env: env.clone(),
+        daily_puzzles: Default::default(),
     };
 
     let state = Arc::new(state); // one shared instance
-    populate_leaderboard(
-        &state.sqlite_pool,
-        &state.redis_pool,
-        "longest",
-        "path_length",
-        state.env.leaderboard_limit,
-    )
-    .await?;
-    populate_leaderboard(
-        &state.sqlite_pool,
-        &state.redis_pool,
-        "most",
-        "num_paths",
-        state.env.leaderboard_limit,
-    )
-    .await?;
+    for wiki in state.datasets.keys() {
+        populate_leaderboard(
+            &state.sqlite_pool,
+            &state.redis_pool,
+            wiki,
+            "longest",
+            "path_length",
+            state.env.leaderboard_limit,
+        )
+        .await?;
+        populate_leaderboard(
+            &state.sqlite_pool,
+            &state.redis_pool,
+            wiki,
+            "most",
+            "num_paths",
+            state.env.leaderboard_limit,
+        )
+        .await?;
+    }
 
     let cors = CorsLayer::new()
         // .allow_origin(Any) // allow all origins (for dev)
@@ -343,7 +607,6 @@ async fn main() -> anyhow::Result<()> {
     //     &redirect_targets_dense,
     //     1000,
     //     255,
-    //     &redirects_passed_mmap,
     // );
 
     // loop {
@@ -375,12 +638,24 @@ use crate::search::*;
 /// Stops on Enter key and saves top paths to a timestamped binary file.
 
 pub fn run_bfs_worker(
+    data: &DataDir,
     num_threads: usize,
     top_k_per_thread: usize,
     top_k_global: usize,
 ) -> anyhow::Result<String> {
     // Load graph
-    let csr_graph = Arc::new(load_csr_graph_mmap()?);
+    let csr_graph = Arc::new(load_graph_backend(
+        data,
+        config::compressed_graph_from_env()?,
+    )?);
+    let search_contexts = Arc::new(SearchContextPool::new(csr_graph.num_nodes()));
+    let landmarks = match load_landmarks_mmap(data) {
+        Ok(landmarks) => Some(Arc::new(landmarks)),
+        Err(e) => {
+            println!("no landmarks, every pair gets a full search: {}", e);
+            None
+        }
+    };
 
     // Stoppable flag
     let stop_flag = Arc::new(AtomicBool::new(false));
@@ -398,14 +673,40 @@ pub fn run_bfs_worker(
         let stop = stop_flag.clone();
         let top_k = top_k_per_thread;
         let bfs_count = bfs_counts.clone();
+        let contexts = search_contexts.clone();
+        let landmarks = landmarks.clone();
         let handle = thread::spawn(move || {
             let mut heap: BinaryHeap<Reverse<(u8, u32, u32)>> = BinaryHeap::new();
             let mut rng = rand::rng();
+            // one context per worker for its whole run
+            let mut ctx = contexts.get();
+            // pressing enter also stops a search that's still running
+            let limits = SearchLimits {
+                cancel: Some(stop.clone()),
+                ..SearchLimits::default()
+            };
             while !stop.load(Ordering::Relaxed) {
                 let start = rng.random_range(0..graph.num_nodes()) as u32;
                 let goal = rng.random_range(0..graph.num_nodes()) as u32;
 
-                if let Some(depth) = bi_bfs_csr_depth_only(&*graph, start, goal) {
+                let bounds = match landmarks.as_ref().map(|l| l.estimate_distance(start, goal)) {
+                    // no path at all
+                    Some(None) => continue,
+                    Some(bounds) => bounds,
+                    None => None,
+                };
+                // can't be longer than the shortest pair kept so far
+                if let (Some(upper), Some(&Reverse((min_depth, _, _)))) =
+                    (bounds.and_then(|b| b.upper), heap.peek())
+                {
+                    if heap.len() >= top_k && upper <= min_depth {
+                        continue;
+                    }
+                }
+
+                if let SearchOutcome::Found(depth) =
+                    bi_bfs_csr_depth_only(&*graph, &mut ctx, start, goal, &limits, bounds)
+                {
                     let rev_item = Reverse((depth, start, goal));
                     if heap.len() < top_k {
                         heap.push(rev_item);
@@ -0,0 +1,212 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::graph::*;
+use crate::mmap_structs::load_csr_graph_mmap;
+use crate::paths::DataDir;
+use crate::util;
+use crate::util::MmapView;
+
+pub fn build_and_save_compressed_csr_mmap(data: &DataDir) -> anyhow::Result<()> {
+    let csr_graph = load_csr_graph_mmap(data)?;
+
+    println!("compressing csr");
+    let encode = |reverse: bool| -> (Vec<u64>, Vec<u8>) {
+        let mut offsets: Vec<u64> = Vec::with_capacity(csr_graph.num_nodes() + 1);
+        let mut bytes: Vec<u8> = Vec::new();
+        for node in 0..csr_graph.num_nodes() as u32 {
+            offsets.push(bytes.len() as u64);
+            let neighbors = if reverse {
+                csr_graph.get_reverse(node)
+            } else {
+                csr_graph.get(node)
+            };
+            encode_neighbors(neighbors, &mut bytes);
+        }
+        offsets.push(bytes.len() as u64);
+        (offsets, bytes)
+    };
+
+    let (offsets, edges) = encode(false);
+    util::write_u64_vec_to_file(&offsets, data, "csr_compressed/offsets.bin")?;
+    util::write_u8_vec_to_file(&edges, data, "csr_compressed/edges.bin")?;
+    drop((offsets, edges));
+
+    let (reverse_offsets, reverse_edges) = encode(true);
+    util::write_u64_vec_to_file(&reverse_offsets, data, "csr_compressed/reverse_offsets.bin")?;
+    util::write_u8_vec_to_file(&reverse_edges, data, "csr_compressed/reverse_edges.bin")?;
+
+    // the edge count can't be read off the varint bytes
+    util::write_u64_vec_to_file(
+        &[csr_graph.num_edges() as u64],
+        data,
+        "csr_compressed/num_edges.bin",
+    )?;
+
+    Ok(())
+}
+
+pub fn load_compressed_csr_graph_mmap(data: &DataDir) -> anyhow::Result<CompressedCsrGraphMmap> {
+    let offsets: MmapView = util::mmap_file::<u64>(data, "csr_compressed/offsets.bin")?;
+    let reverse_offsets: MmapView =
+        util::mmap_file::<u64>(data, "csr_compressed/reverse_offsets.bin")?;
+    let edges_mmap: MmapView = util::mmap_file::<u8>(data, "csr_compressed/edges.bin")?;
+    let reverse_edges_mmap: MmapView =
+        util::mmap_file::<u8>(data, "csr_compressed/reverse_edges.bin")?;
+    reverse_offsets.expect_count(offsets.header.count)?;
+    edges_mmap.expect_count(offsets.last_offset())?;
+    reverse_edges_mmap.expect_count(reverse_offsets.last_offset())?;
+    let num_edges = util::mmap_as_u64_slice(&util::mmap_file::<u64>(
+        data,
+        "csr_compressed/num_edges.bin",
+    )?)
+    .first()
+    .copied()
+    .ok_or_else(|| anyhow::anyhow!("{} is empty", data.file("csr_compressed/num_edges.bin")))?;
+
+    Ok(CompressedCsrGraphMmap {
+        offsets,
+        reverse_offsets,
+        edges_mmap,
+        reverse_edges_mmap,
+        num_edges: num_edges as usize,
+    })
+}
+
+/// The plain or the compressed graph, for code that should search either
+pub fn load_graph_backend(data: &DataDir, compressed: bool) -> anyhow::Result<GraphBackend> {
+    Ok(if compressed {
+        GraphBackend::Compressed(load_compressed_csr_graph_mmap(data)?)
+    } else {
+        GraphBackend::Plain(load_csr_graph_mmap(data)?)
+    })
+}
+
+/// Compare the compressed graph with the plain one: size on disk, how much of it ends up
+/// in memory, and full bfs speed from the same random pages on both
+pub fn print_compressed_csr_report(data: &DataDir, num_sources: usize) -> anyhow::Result<()> {
+    let plain = load_csr_graph_mmap(data)?;
+    let compressed = load_compressed_csr_graph_mmap(data)?;
+    let num_nodes = plain.num_nodes();
+    if compressed.num_nodes() != num_nodes || compressed.num_edges() != plain.num_edges() {
+        anyhow::bail!("compressed graph is out of date, run --build-compressed-csr again");
+    }
+
+    let mut rng = rand::rng();
+    let sources: Vec<u32> = (0..num_sources)
+        .map(|_| rng.random_range(0..num_nodes) as u32)
+        .collect();
+
+    let (plain_edges, plain_time) = bfs_throughput(&plain, num_nodes, &sources);
+    let (compressed_edges, compressed_time) = bfs_throughput(&compressed, num_nodes, &sources);
+    if plain_edges != compressed_edges {
+        println!(
+            "MISMATCH: plain scanned {} edges, compressed {}",
+            plain_edges, compressed_edges
+        );
+    }
+
+    for (name, dir, edges, time) in [
+        ("plain", &data.file("csr"), plain_edges, plain_time),
+        (
+            "compressed",
+            &data.file("csr_compressed"),
+            compressed_edges,
+            compressed_time,
+        ),
+    ] {
+        println!("{}:", name);
+        println!("  on disk: {:.1} MiB", dir_size(dir)? as f64 / 1048576.0);
+        match resident_bytes(dir) {
+            Some(bytes) => println!("  resident: {:.1} MiB", bytes as f64 / 1048576.0),
+            None => println!("  resident: unknown (needs /proc/self/smaps)"),
+        }
+        println!(
+            "  bfs: {} edges in {:.2?} ({:.1}M edges/s)",
+            edges,
+            time,
+            edges as f64 / time.as_secs_f64().max(1e-9) / 1e6
+        );
+    }
+    Ok(())
+}
+
+/// Bfs over the whole graph from each source, forwards then backwards.
+/// Returns the edges scanned and the time.
+fn bfs_throughput<G: CsrGraphTrait>(
+    graph: &G,
+    num_nodes: usize,
+    sources: &[u32],
+) -> (u64, Duration) {
+    let mut visited = vec![false; num_nodes];
+    let mut frontier: Vec<u32> = Vec::new();
+    let mut next_frontier: Vec<u32> = Vec::new();
+    let mut edges_scanned: u64 = 0;
+
+    let start_time = Instant::now();
+    for &source in sources {
+        for backwards in [false, true] {
+            visited.fill(false);
+            visited[source as usize] = true;
+            frontier.clear();
+            frontier.push(source);
+            while !frontier.is_empty() {
+                for &node in &frontier {
+                    let mut visit = |neighbor: u32| {
+                        edges_scanned += 1;
+                        if !visited[neighbor as usize] {
+                            visited[neighbor as usize] = true;
+                            next_frontier.push(neighbor);
+                        }
+                    };
+                    if backwards {
+                        graph.reverse_neighbors(node).for_each(&mut visit);
+                    } else {
+                        graph.neighbors(node).for_each(&mut visit);
+                    }
+                }
+                std::mem::swap(&mut frontier, &mut next_frontier);
+                next_frontier.clear();
+            }
+        }
+    }
+    (edges_scanned, start_time.elapsed())
+}
+
+fn dir_size(dir: &str) -> anyhow::Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        total += entry?.metadata()?.len();
+    }
+    Ok(total)
+}
+
+/// Resident bytes of this process's mappings of files in `dir`, from /proc/self/smaps (linux only)
+fn resident_bytes(dir: &str) -> Option<u64> {
+    let dir = format!("{}/", fs::canonicalize(dir).ok()?.to_str()?);
+    let smaps = fs::read_to_string("/proc/self/smaps").ok()?;
+
+    let mut total = 0;
+    let mut in_dir = false;
+    for line in smaps.lines() {
+        if let Some(rss) = line.strip_prefix("Rss:") {
+            if in_dir {
+                let kb: u64 = rss.trim().trim_end_matches("kB").trim().parse().ok()?;
+                total += kb * 1024;
+            }
+        } else if line
+            .split_whitespace()
+            .next()
+            .is_some_and(|field| field.contains('-') && !field.ends_with(':'))
+        {
+            // a mapping header, the path is the last field
+            in_dir = line
+                .split_whitespace()
+                .last()
+                .is_some_and(|path| path.starts_with(&dir));
+        }
+    }
+    Some(total)
+}
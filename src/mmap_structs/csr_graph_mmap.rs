@@ -1,15 +1,17 @@
-use memmap2::Mmap;
 use rustc_hash::FxHashMap;
 
 use crate::graph::*;
+use crate::paths::DataDir;
 use crate::util;
+use crate::util::MmapView;
 
-pub fn build_and_save_pagelinks_csr() -> anyhow::Result<()> {
+pub fn build_and_save_pagelinks_csr(data: &DataDir) -> anyhow::Result<()> {
     let pagelinks_adjacency_list: FxHashMap<u32, Vec<u32>> =
-        util::load_from_file("data/pagelinks_adjacency_list.bin")?;
+        util::load_from_file(&data.file("pagelinks_adjacency_list.bin"))?;
     let incoming_pagelinks_adjacency_list: FxHashMap<u32, Vec<u32>> =
-        util::load_from_file("data/incoming_pagelinks_adjacency_list.bin")?;
-    let orig_to_dense_id: FxHashMap<u32, u32> = util::load_from_file("data/orig_to_dense_id.bin")?;
+        util::load_from_file(&data.file("incoming_pagelinks_adjacency_list.bin"))?;
+    let orig_to_dense_id: FxHashMap<u32, u32> =
+        util::load_from_file(&data.file("orig_to_dense_id.bin"))?;
 
     println!("building csr");
     let pagelinks_csr: CsrGraph = build_csr_with_adjacency_list(
@@ -23,33 +25,48 @@ pub fn build_and_save_pagelinks_csr() -> anyhow::Result<()> {
     drop(orig_to_dense_id);
 
     // in memory version
-    util::save_to_file(&pagelinks_csr, "data/pagelinks_csr.bin")?;
+    util::save_to_file(&pagelinks_csr, &data.file("pagelinks_csr.bin"))?;
 
     // memory mappable version
-    util::write_u32_vec_to_file(&pagelinks_csr.edges, "data/csr/edges.bin")?;
-    util::write_u32_vec_to_file(&pagelinks_csr.reverse_edges, "data/csr/reverse_edges.bin")?;
+    util::write_u32_vec_to_file(&pagelinks_csr.edges, data, "csr/edges.bin")?;
+    util::write_u32_vec_to_file(&pagelinks_csr.reverse_edges, data, "csr/reverse_edges.bin")?;
 
-    util::write_u32_vec_to_file(&pagelinks_csr.offsets, "data/csr/offsets.bin")?;
+    util::write_u32_vec_to_file(&pagelinks_csr.offsets, data, "csr/offsets.bin")?;
     util::write_u32_vec_to_file(
         &pagelinks_csr.reverse_offsets,
-        "data/csr/reverse_offsets.bin",
+        data,
+        "csr/reverse_offsets.bin",
     )?;
 
     Ok(())
 }
 
-pub fn load_csr_graph_mmap() -> anyhow::Result<CsrGraphMmap> {
+pub fn load_csr_graph_mmap(data: &DataDir) -> anyhow::Result<CsrGraphMmap> {
     // Memory-map the big edge arrays
-    let edges_mmap: Mmap = util::mmap_file("data/csr/edges.bin")?;
-    let reverse_edges_mmap: Mmap = util::mmap_file("data/csr/reverse_edges.bin")?;
+    let edges_mmap: MmapView = util::mmap_file::<u32>(data, "csr/edges.bin")?;
+    let reverse_edges_mmap: MmapView = util::mmap_file::<u32>(data, "csr/reverse_edges.bin")?;
 
-    let offsets: Mmap = util::mmap_file("data/csr/offsets.bin")?;
-    let reverse_offsets: Mmap = util::mmap_file("data/csr/reverse_offsets.bin")?;
+    let offsets: MmapView = util::mmap_file::<u32>(data, "csr/offsets.bin")?;
+    let reverse_offsets: MmapView = util::mmap_file::<u32>(data, "csr/reverse_offsets.bin")?;
 
-    Ok(CsrGraphMmap {
+    let csr_graph = CsrGraphMmap {
         offsets,
         reverse_offsets,
         edges_mmap,
         reverse_edges_mmap,
-    })
+    };
+    check_csr_mmap(&csr_graph)?;
+    Ok(csr_graph)
+}
+
+/// Offsets that don't match their edges would read past the end of them on some node
+pub fn check_csr_mmap(graph: &CsrGraphMmap) -> anyhow::Result<()> {
+    graph
+        .reverse_offsets
+        .expect_count(graph.offsets.header.count)?;
+    graph.edges_mmap.expect_count(graph.offsets.last_offset())?;
+    graph
+        .reverse_edges_mmap
+        .expect_count(graph.reverse_offsets.last_offset())?;
+    Ok(())
 }
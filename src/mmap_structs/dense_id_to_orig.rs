@@ -1,8 +1,9 @@
+use crate::paths::DataDir;
 use crate::util;
-use memmap2::Mmap;
+use crate::util::MmapView;
 
 pub struct DenseIdToOrigMmap {
-    pub orig_ids: Mmap, // Vec<u32>
+    pub orig_ids: MmapView, // Vec<u32>
 }
 
 impl DenseIdToOrigMmap {
@@ -12,16 +13,16 @@ impl DenseIdToOrigMmap {
     }
 }
 
-pub fn build_and_save_dense_id_to_orig_mmap() -> anyhow::Result<()> {
-    let dense_id_to_orig: Vec<u32> = util::load_from_file("data/dense_id_to_orig.bin")?;
+pub fn build_and_save_dense_id_to_orig_mmap(data: &DataDir) -> anyhow::Result<()> {
+    let dense_id_to_orig: Vec<u32> = util::load_from_file(&data.file("dense_id_to_orig.bin"))?;
 
     // Save to disk as raw u32 bytes
-    util::write_u32_vec_to_file(&dense_id_to_orig, "data/dense_id_to_orig/mmap.bin")?;
+    util::write_u32_vec_to_file(&dense_id_to_orig, data, "dense_id_to_orig/mmap.bin")?;
 
     Ok(())
 }
 
-pub fn load_dense_id_to_orig_mmap() -> anyhow::Result<DenseIdToOrigMmap> {
-    let orig_ids: Mmap = util::mmap_file("data/dense_id_to_orig/mmap.bin")?;
+pub fn load_dense_id_to_orig_mmap(data: &DataDir) -> anyhow::Result<DenseIdToOrigMmap> {
+    let orig_ids: MmapView = util::mmap_file::<u32>(data, "dense_id_to_orig/mmap.bin")?;
     Ok(DenseIdToOrigMmap { orig_ids })
 }
@@ -1,9 +1,10 @@
+use crate::paths::DataDir;
 use crate::util;
-use memmap2::Mmap;
+use crate::util::MmapView;
 
 pub struct DenseIdToTitleMmap {
-    pub titles: Mmap,  // Vec<u8> character blob
-    pub offsets: Mmap, // Vec<u32>
+    pub titles: MmapView,  // Vec<u8> character blob
+    pub offsets: MmapView, // Vec<u32>
 }
 
 impl DenseIdToTitleMmap {
@@ -15,8 +16,8 @@ impl DenseIdToTitleMmap {
     }
 }
 
-pub fn build_and_save_dense_id_to_title_mmap() -> anyhow::Result<()> {
-    let dense_id_to_title: Vec<String> = util::load_from_file("data/dense_id_to_title.bin")?;
+pub fn build_and_save_dense_id_to_title_mmap(data: &DataDir) -> anyhow::Result<()> {
+    let dense_id_to_title: Vec<String> = util::load_from_file(&data.file("dense_id_to_title.bin"))?;
 
     // Prepare the flat titles buffer and offsets
     let mut titles: Vec<u8> = Vec::new();
@@ -29,15 +30,16 @@ pub fn build_and_save_dense_id_to_title_mmap() -> anyhow::Result<()> {
     }
 
     // Save to disk
-    util::write_u8_vec_to_file(&titles, "data/dense_id_to_title/titles.bin")?;
-    util::write_u32_vec_to_file(&offsets, "data/dense_id_to_title/offsets.bin")?;
+    util::write_u8_vec_to_file(&titles, data, "dense_id_to_title/titles.bin")?;
+    util::write_u32_vec_to_file(&offsets, data, "dense_id_to_title/offsets.bin")?;
 
     Ok(())
 }
 
-pub fn load_dense_id_to_title_mmap() -> anyhow::Result<DenseIdToTitleMmap> {
-    let titles: Mmap = util::mmap_file("data/dense_id_to_title/titles.bin")?;
-    let offsets: Mmap = util::mmap_file("data/dense_id_to_title/offsets.bin")?;
+pub fn load_dense_id_to_title_mmap(data: &DataDir) -> anyhow::Result<DenseIdToTitleMmap> {
+    let titles: MmapView = util::mmap_file::<u8>(data, "dense_id_to_title/titles.bin")?;
+    let offsets: MmapView = util::mmap_file::<u32>(data, "dense_id_to_title/offsets.bin")?;
+    titles.expect_count(offsets.last_offset())?;
 
     Ok(DenseIdToTitleMmap { titles, offsets })
 }
@@ -0,0 +1,131 @@
+use std::fs;
+
+use crate::graph::CsrGraphTrait;
+use crate::paths::DataDir;
+use crate::util;
+use crate::util::MmapView;
+
+const HUB_DEGREES_FILE: &str = "hub_degrees/hub_degrees.bin";
+// the csr file the table is computed from, the table is rebuilt when it's newer
+const CSR_OFFSETS_FILE: &str = "csr/offsets.bin";
+// only the biggest hubs are kept, so the table stays small. searches asking for more are refused
+pub const HUB_TABLE_SIZE: usize = 100_000;
+
+/// The nodes with the highest degree, as (dense_id, in_degree, out_degree) u32 triples
+/// sorted by max(in_degree, out_degree), highest first.
+pub struct HubDegreesMmap {
+    pub mmap: MmapView, // Vec<u32>
+}
+
+impl HubDegreesMmap {
+    pub fn num_hubs(&self) -> usize {
+        self.mmap.len() / 12
+    }
+
+    /// (dense_id, in_degree, out_degree) of the i-th biggest hub
+    pub fn get(&self, i: usize) -> (u32, u32, u32) {
+        let records: &[u32] = util::mmap_as_u32_slice(&self.mmap);
+        (records[i * 3], records[i * 3 + 1], records[i * 3 + 2])
+    }
+
+    /// Larger of the in and out degree of the i-th biggest hub
+    pub fn degree(&self, i: usize) -> u32 {
+        let (_, in_degree, out_degree) = self.get(i);
+        in_degree.max(out_degree)
+    }
+
+    /// Number of hubs with an in or out degree above max_degree,
+    /// none when there are more of them than the table holds
+    pub fn count_above_degree(&self, max_degree: u32, num_nodes: usize) -> Option<usize> {
+        // degrees are sorted descending
+        let (mut lo, mut hi) = (0, self.num_hubs());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.degree(mid) > max_degree {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        // a full table ends above max_degree when hubs past it were cut off
+        (lo < self.num_hubs() || self.num_hubs() == num_nodes).then_some(lo)
+    }
+
+    /// Number of hubs above the given degree percentile of all num_nodes nodes,
+    /// none when there are more of them than the table holds
+    pub fn count_above_percentile(&self, percentile: f64, num_nodes: usize) -> Option<usize> {
+        let above = (num_nodes as f64 * (100.0 - percentile) / 100.0).ceil() as usize;
+        (above <= self.num_hubs()).then_some(above)
+    }
+
+    /// Dense ids of the `count` biggest hubs
+    pub fn top(&self, count: usize) -> Vec<u32> {
+        (0..count.min(self.num_hubs()))
+            .map(|i| self.get(i).0)
+            .collect()
+    }
+}
+
+pub fn build_and_save_hub_degrees_mmap<G: CsrGraphTrait>(
+    data: &DataDir,
+    graph: &G,
+) -> anyhow::Result<()> {
+    println!("computing hub degrees");
+    let mut degrees: Vec<(u32, u32, u32)> = (0..graph.num_nodes() as u32)
+        .map(|node| {
+            (
+                node,
+                graph.reverse_degree(node) as u32,
+                graph.degree(node) as u32,
+            )
+        })
+        .collect();
+    // highest degree first, ties by dense id so the table is deterministic
+    degrees.sort_unstable_by_key(|&(node, in_degree, out_degree)| {
+        (std::cmp::Reverse(in_degree.max(out_degree)), node)
+    });
+    degrees.truncate(HUB_TABLE_SIZE);
+
+    let records: Vec<u32> = degrees
+        .into_iter()
+        .flat_map(|(node, in_degree, out_degree)| [node, in_degree, out_degree])
+        .collect();
+    util::write_u32_vec_to_file(&records, data, HUB_DEGREES_FILE)?;
+    Ok(())
+}
+
+/// Load the hub table, computing it first if it's missing or older than the csr graph
+pub fn load_or_build_hub_degrees_mmap<G: CsrGraphTrait>(
+    data: &DataDir,
+    graph: &G,
+) -> anyhow::Result<HubDegreesMmap> {
+    // packed when the bundle was made, and there's nowhere to write a new one
+    if data.bundle().is_some() {
+        let mmap: MmapView = util::mmap_file::<u32>(data, HUB_DEGREES_FILE)?;
+        return Ok(HubDegreesMmap { mmap });
+    }
+
+    let modified = |path: &str| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let table_path = data.file(HUB_DEGREES_FILE);
+    let stale = match (
+        modified(&table_path),
+        modified(&data.file(CSR_OFFSETS_FILE)),
+    ) {
+        (Some(table), Some(csr)) => table < csr,
+        (Some(_), None) => false,
+        (None, _) => true,
+    };
+    if stale {
+        build_and_save_hub_degrees_mmap(data, graph)?;
+    }
+
+    let mmap: MmapView = match util::mmap_file::<u32>(data, HUB_DEGREES_FILE) {
+        Ok(mmap) => mmap,
+        // written by an older version or for another build, it's cheap to redo
+        Err(_) => {
+            build_and_save_hub_degrees_mmap(data, graph)?;
+            util::mmap_file::<u32>(data, HUB_DEGREES_FILE)?
+        }
+    };
+    Ok(HubDegreesMmap { mmap })
+}
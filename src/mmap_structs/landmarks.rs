@@ -0,0 +1,205 @@
+use serde::Serialize;
+
+use crate::graph::*;
+use crate::mmap_structs::load_csr_graph_mmap;
+use crate::paths::DataDir;
+use crate::util;
+use crate::util::MmapView;
+
+pub const DEFAULT_NUM_LANDMARKS: usize = 8;
+// distance stored for nodes a landmark can't reach (or that can't reach it)
+const UNREACHABLE: u8 = u8::MAX;
+// distances this long or longer are all stored as this, so it only means "at least"
+const FAR: u8 = UNREACHABLE - 1;
+
+/// BFS distances from and to a few landmark pages, for bounding the distance between
+/// any two pages with the triangle inequality (ALT). Distances are node-major,
+/// `dist_from[node * num_landmarks + i]` is the distance from landmark i to node.
+pub struct LandmarksMmap {
+    pub landmarks: MmapView, // Vec<u32>, dense ids
+    pub dist_from: MmapView, // Vec<u8>, landmark -> node
+    pub dist_to: MmapView,   // Vec<u8>, node -> landmark
+}
+
+/// Bounds on the number of links between two pages. `upper` is none when no landmark
+/// is on a route between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DistanceBounds {
+    pub lower: u8,
+    pub upper: Option<u8>,
+}
+
+impl DistanceBounds {
+    pub fn is_exact(&self) -> bool {
+        self.upper == Some(self.lower)
+    }
+}
+
+impl LandmarksMmap {
+    pub fn num_landmarks(&self) -> usize {
+        self.landmarks.len() / 4
+    }
+
+    /// Distance bounds from start to goal, or none when the landmarks prove there's no path
+    pub fn estimate_distance(&self, start: u32, goal: u32) -> Option<DistanceBounds> {
+        if start == goal {
+            return Some(DistanceBounds {
+                lower: 0,
+                upper: Some(0),
+            });
+        }
+
+        let k = self.num_landmarks();
+        let from_start = &self.dist_from[start as usize * k..(start as usize + 1) * k];
+        let from_goal = &self.dist_from[goal as usize * k..(goal as usize + 1) * k];
+        let to_start = &self.dist_to[start as usize * k..(start as usize + 1) * k];
+        let to_goal = &self.dist_to[goal as usize * k..(goal as usize + 1) * k];
+
+        let mut lower: u8 = 1;
+        let mut upper: Option<u8> = None;
+        for i in 0..k {
+            // capped distances only get smaller, so the lower bounds still hold with them
+            // landmark -> start -> goal, so d(L, goal) <= d(L, start) + d(start, goal)
+            if from_start[i] != UNREACHABLE {
+                if from_goal[i] == UNREACHABLE {
+                    return None;
+                }
+                lower = lower.max(from_goal[i].saturating_sub(from_start[i]));
+            }
+            // start -> goal -> landmark, so d(start, L) <= d(start, goal) + d(goal, L)
+            if to_goal[i] != UNREACHABLE {
+                if to_start[i] == UNREACHABLE {
+                    return None;
+                }
+                lower = lower.max(to_start[i].saturating_sub(to_goal[i]));
+            }
+            // start -> landmark -> goal is a real route, unless a leg is capped
+            if to_start[i] < FAR && from_goal[i] < FAR {
+                if let Some(through) = to_start[i].checked_add(from_goal[i]).filter(|&d| d < FAR) {
+                    upper = Some(upper.map_or(through, |upper| upper.min(through)));
+                }
+            }
+        }
+
+        Some(DistanceBounds { lower, upper })
+    }
+}
+
+/// Depth of every node from `source` (UNREACHABLE if it can't be reached). Depths fit in
+/// a u8 like everywhere else in the search, anything past FAR is stored as FAR.
+fn bfs_distances<G: CsrGraphTrait>(graph: &G, source: u32, backwards: bool) -> Vec<u8> {
+    let mut dist = vec![UNREACHABLE; graph.num_nodes()];
+    dist[source as usize] = 0;
+    let mut frontier = vec![source];
+    let mut next_frontier = Vec::new();
+    let mut depth: u8 = 0;
+    while !frontier.is_empty() {
+        depth = (depth + 1).min(FAR);
+        for &node in &frontier {
+            let neighbors = if backwards {
+                graph.reverse_neighbors(node)
+            } else {
+                graph.neighbors(node)
+            };
+            for neighbor in neighbors {
+                if dist[neighbor as usize] == UNREACHABLE {
+                    dist[neighbor as usize] = depth;
+                    next_frontier.push(neighbor);
+                }
+            }
+        }
+        std::mem::swap(&mut frontier, &mut next_frontier);
+        next_frontier.clear();
+    }
+    dist
+}
+
+/// Half the landmarks are the most linked pages, the rest are picked one at a time as the
+/// page farthest from every landmark so far (farthest-point), so they cover the edges of the graph too.
+pub fn build_and_save_landmarks_mmap(data: &DataDir, num_landmarks: usize) -> anyhow::Result<()> {
+    if num_landmarks == 0 {
+        anyhow::bail!("need at least one landmark");
+    }
+    let csr_graph = load_csr_graph_mmap(data)?;
+    let num_nodes = csr_graph.num_nodes();
+
+    let mut by_degree: Vec<u32> = (0..num_nodes as u32).collect();
+    by_degree.sort_unstable_by_key(|&node| {
+        let degree = csr_graph.degree(node) + csr_graph.reverse_degree(node);
+        (std::cmp::Reverse(degree), node)
+    });
+
+    let mut landmarks: Vec<u32> = Vec::with_capacity(num_landmarks);
+    let mut dists_from: Vec<Vec<u8>> = Vec::with_capacity(num_landmarks);
+    let mut dists_to: Vec<Vec<u8>> = Vec::with_capacity(num_landmarks);
+    // distance from the closest landmark to every node
+    let mut closest = vec![UNREACHABLE; num_nodes];
+
+    while landmarks.len() < num_landmarks.min(num_nodes) {
+        // rounded up, a single landmark is the most linked page
+        let landmark = if landmarks.len() < num_landmarks.div_ceil(2) {
+            by_degree[landmarks.len()]
+        } else {
+            // ties go to the lowest id so the pick is deterministic
+            match (0..num_nodes)
+                .filter(|&node| closest[node] != UNREACHABLE)
+                .max_by_key(|&node| (closest[node], std::cmp::Reverse(node)))
+            {
+                Some(node) if closest[node] > 0 => node as u32,
+                // every reachable node is already a landmark
+                _ => break,
+            }
+        };
+        println!(
+            "landmark {}/{}: dense id {}",
+            landmarks.len() + 1,
+            num_landmarks,
+            landmark
+        );
+
+        let dist_from = bfs_distances(&csr_graph, landmark, false);
+        for (closest, &dist) in closest.iter_mut().zip(&dist_from) {
+            *closest = (*closest).min(dist);
+        }
+        dists_from.push(dist_from);
+        dists_to.push(bfs_distances(&csr_graph, landmark, true));
+        landmarks.push(landmark);
+    }
+
+    // node-major, so one estimate reads two short runs of bytes per node
+    let interleave = |dists: &[Vec<u8>]| -> Vec<u8> {
+        let mut out = Vec::with_capacity(num_nodes * dists.len());
+        for node in 0..num_nodes {
+            out.extend(dists.iter().map(|dist| dist[node]));
+        }
+        out
+    };
+
+    util::write_u32_vec_to_file(&landmarks, data, "landmarks/landmarks.bin")?;
+    util::write_u8_vec_to_file(&interleave(&dists_from), data, "landmarks/dist_from.bin")?;
+    util::write_u8_vec_to_file(&interleave(&dists_to), data, "landmarks/dist_to.bin")?;
+    Ok(())
+}
+
+pub fn load_landmarks_mmap(data: &DataDir) -> anyhow::Result<LandmarksMmap> {
+    let landmarks: MmapView = util::mmap_file::<u32>(data, "landmarks/landmarks.bin")?;
+    let dist_from: MmapView = util::mmap_file::<u8>(data, "landmarks/dist_from.bin")?;
+    let dist_to: MmapView = util::mmap_file::<u8>(data, "landmarks/dist_to.bin")?;
+    dist_to.expect_count(dist_from.header.count)?;
+    if landmarks.header.count == 0
+        || !dist_from
+            .header
+            .count
+            .is_multiple_of(landmarks.header.count)
+    {
+        anyhow::bail!(
+            "{} doesn't hold a distance per landmark for every page, rebuild the landmarks",
+            dist_from.path
+        );
+    }
+    Ok(LandmarksMmap {
+        landmarks,
+        dist_from,
+        dist_to,
+    })
+}
@@ -1,16 +1,24 @@
+pub mod compressed_csr_mmap;
 pub mod csr_graph_mmap;
 pub mod dense_id_to_orig;
 pub mod dense_id_to_title;
+pub mod hub_degrees;
+pub mod landmarks;
 pub mod orig_to_dense_id;
 pub mod redirect_targets_dense;
 pub mod redirects_passed;
+pub mod scc;
 pub mod title_to_dense_id;
 
 // pub use csr_graph_mmap::*;
+pub use compressed_csr_mmap::*;
 pub use csr_graph_mmap::*;
 pub use dense_id_to_orig::*;
 pub use dense_id_to_title::*;
+pub use hub_degrees::*;
+pub use landmarks::*;
 pub use orig_to_dense_id::*;
 pub use redirect_targets_dense::*;
 pub use redirects_passed::*;
+pub use scc::*;
 pub use title_to_dense_id::*;
@@ -1,11 +1,12 @@
+use crate::paths::DataDir;
 use crate::util;
-use memmap2::Mmap;
+use crate::util::MmapView;
 use rustc_hash::FxHashMap;
 use std::cmp::Ordering;
 
 pub struct OrigToDenseIdMmap {
-    pub orig_ids: Mmap,  // Vec<u32>, sorted original IDs
-    pub dense_ids: Mmap, // Vec<u32>, parallel to orig_ids
+    pub orig_ids: MmapView,  // Vec<u32>, sorted original IDs
+    pub dense_ids: MmapView, // Vec<u32>, parallel to orig_ids
 }
 
 impl OrigToDenseIdMmap {
@@ -31,8 +32,9 @@ impl OrigToDenseIdMmap {
 }
 
 /// Build the memory-mapped structure
-pub fn build_and_save_orig_to_dense_id_mmap() -> anyhow::Result<()> {
-    let orig_to_dense_id: FxHashMap<u32, u32> = util::load_from_file("data/orig_to_dense_id.bin")?;
+pub fn build_and_save_orig_to_dense_id_mmap(data: &DataDir) -> anyhow::Result<()> {
+    let orig_to_dense_id: FxHashMap<u32, u32> =
+        util::load_from_file(&data.file("orig_to_dense_id.bin"))?;
 
     // Collect and sort by original ID
     let mut entries: Vec<(&u32, &u32)> = orig_to_dense_id.iter().collect();
@@ -47,16 +49,17 @@ pub fn build_and_save_orig_to_dense_id_mmap() -> anyhow::Result<()> {
     }
 
     // Save to disk
-    util::write_u32_vec_to_file(&orig_ids, "data/orig_to_dense_id/orig_ids.bin")?;
-    util::write_u32_vec_to_file(&dense_ids, "data/orig_to_dense_id/dense_ids.bin")?;
+    util::write_u32_vec_to_file(&orig_ids, data, "orig_to_dense_id/orig_ids.bin")?;
+    util::write_u32_vec_to_file(&dense_ids, data, "orig_to_dense_id/dense_ids.bin")?;
 
     Ok(())
 }
 
 /// Load the memory-mapped structure
-pub fn load_orig_to_dense_id_mmap() -> anyhow::Result<OrigToDenseIdMmap> {
-    let orig_ids = util::mmap_file("data/orig_to_dense_id/orig_ids.bin")?;
-    let dense_ids = util::mmap_file("data/orig_to_dense_id/dense_ids.bin")?;
+pub fn load_orig_to_dense_id_mmap(data: &DataDir) -> anyhow::Result<OrigToDenseIdMmap> {
+    let orig_ids = util::mmap_file::<u32>(data, "orig_to_dense_id/orig_ids.bin")?;
+    let dense_ids = util::mmap_file::<u32>(data, "orig_to_dense_id/dense_ids.bin")?;
+    dense_ids.expect_count(orig_ids.header.count)?;
 
     Ok(OrigToDenseIdMmap {
         orig_ids,
@@ -1,9 +1,10 @@
+use crate::paths::DataDir;
 use crate::util;
-use memmap2::Mmap;
+use crate::util::MmapView;
 
 pub struct RedirectTargetsDenseMmap {
-    pub mmap: Mmap, // raw bytes
-                    // pub len: usize, // number of u32 elements
+    pub mmap: MmapView, // raw bytes
+                        // pub len: usize, // number of u32 elements
 }
 
 impl RedirectTargetsDenseMmap {
@@ -19,17 +20,22 @@ impl RedirectTargetsDenseMmap {
     }
 }
 
-pub fn build_and_save_redirect_targets_dense_mmap() -> anyhow::Result<()> {
-    let redirect_targets_dense: Vec<u32> = util::load_from_file("data/redirect_targets_dense.bin")?;
+pub fn build_and_save_redirect_targets_dense_mmap(data: &DataDir) -> anyhow::Result<()> {
+    let redirect_targets_dense: Vec<u32> =
+        util::load_from_file(&data.file("redirect_targets_dense.bin"))?;
     util::write_u32_vec_to_file(
         &redirect_targets_dense,
-        "data/redirect_targets_dense/redirect_targets_dense.bin",
+        data,
+        "redirect_targets_dense/redirect_targets_dense.bin",
     )?;
     Ok(())
 }
 
-pub fn load_redirect_targets_dense_mmap() -> anyhow::Result<RedirectTargetsDenseMmap> {
-    let mmap: Mmap = util::mmap_file("data/redirect_targets_dense/redirect_targets_dense.bin")?;
+pub fn load_redirect_targets_dense_mmap(
+    data: &DataDir,
+) -> anyhow::Result<RedirectTargetsDenseMmap> {
+    let mmap: MmapView =
+        util::mmap_file::<u32>(data, "redirect_targets_dense/redirect_targets_dense.bin")?;
     // let len = mmap.len() / 4; // number of u32 elements
     Ok(RedirectTargetsDenseMmap { mmap })
 }
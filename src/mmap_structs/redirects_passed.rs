@@ -1,14 +1,15 @@
+use crate::paths::DataDir;
 use crate::util;
-use memmap2::Mmap;
+use crate::util::MmapView;
 use rustc_hash::FxHashMap;
 use std::cmp::Ordering;
 
 // going to replace pub redirects_passed: FxHashMap<(u32, u32), u32>, inside of csr_graph
 pub struct RedirectsPassedMmap {
-    pub offsets: Mmap,               // Vec<u32>
-    pub redirect_targets_mmap: Mmap, // Vec<u32>
-    pub redirects_mmap: Mmap,        // Vec<u32>
-                                     // dense ids
+    pub offsets: MmapView,               // Vec<u32>
+    pub redirect_targets_mmap: MmapView, // Vec<u32>
+    pub redirects_mmap: MmapView,        // Vec<u32>
+                                         // dense ids
 }
 
 impl RedirectsPassedMmap {
@@ -41,11 +42,11 @@ impl RedirectsPassedMmap {
     }
 }
 
-pub fn build_and_save_redirects_passed_mmap() -> anyhow::Result<()> {
+pub fn build_and_save_redirects_passed_mmap(data: &DataDir) -> anyhow::Result<()> {
     // Load original data
     let redirects_passed_dense: FxHashMap<(u32, u32), u32> =
-        util::load_from_file("data/redirects_passed_dense.bin")?;
-    let dense_id_to_orig: Vec<u32> = util::load_from_file("data/dense_id_to_orig.bin")?;
+        util::load_from_file(&data.file("redirects_passed_dense.bin"))?;
+    let dense_id_to_orig: Vec<u32> = util::load_from_file(&data.file("dense_id_to_orig.bin"))?;
     let num_pages = dense_id_to_orig.len();
 
     // Step 1: group by page_from
@@ -76,20 +77,24 @@ pub fn build_and_save_redirects_passed_mmap() -> anyhow::Result<()> {
     }
 
     // Step 4: save to disk
-    util::write_u32_vec_to_file(&offsets, "data/redirects_passed/offsets.bin")?;
+    util::write_u32_vec_to_file(&offsets, data, "redirects_passed/offsets.bin")?;
     util::write_u32_vec_to_file(
         &redirect_targets,
-        "data/redirects_passed/redirect_targets.bin",
+        data,
+        "redirects_passed/redirect_targets.bin",
     )?;
-    util::write_u32_vec_to_file(&redirects, "data/redirects_passed/redirects.bin")?;
+    util::write_u32_vec_to_file(&redirects, data, "redirects_passed/redirects.bin")?;
 
     Ok(())
 }
 
-pub fn load_redirects_passed_mmap() -> anyhow::Result<RedirectsPassedMmap> {
-    let offsets = util::mmap_file("data/redirects_passed/offsets.bin")?;
-    let redirect_targets_mmap = util::mmap_file("data/redirects_passed/redirect_targets.bin")?;
-    let redirects_mmap = util::mmap_file("data/redirects_passed/redirects.bin")?;
+pub fn load_redirects_passed_mmap(data: &DataDir) -> anyhow::Result<RedirectsPassedMmap> {
+    let offsets = util::mmap_file::<u32>(data, "redirects_passed/offsets.bin")?;
+    let redirect_targets_mmap =
+        util::mmap_file::<u32>(data, "redirects_passed/redirect_targets.bin")?;
+    let redirects_mmap = util::mmap_file::<u32>(data, "redirects_passed/redirects.bin")?;
+    redirect_targets_mmap.expect_count(offsets.last_offset())?;
+    redirects_mmap.expect_count(offsets.last_offset())?;
 
     Ok(RedirectsPassedMmap {
         offsets,
@@ -0,0 +1,136 @@
+use rustc_hash::FxHashSet;
+
+use crate::graph::*;
+use crate::mmap_structs::{check_csr_mmap, load_csr_graph_mmap};
+use crate::paths::DataDir;
+use crate::util;
+use crate::util::MmapView;
+
+// components looked at when searching the condensation for a route before giving up on a proof
+const DAG_SEARCH_LIMIT: usize = 10_000;
+
+/// Strongly connected components of the link graph. Ids are in topological order of the
+/// condensation dag, so a link between two components always goes to the higher id.
+pub struct SccMmap {
+    pub component_ids: MmapView, // Vec<u32>, one per dense id
+    // one node per component
+    pub dag: CsrGraphMmap,
+}
+
+pub struct SccStats {
+    pub num_components: usize,
+    pub giant_component: u32,
+    pub giant_size: usize,
+    pub num_singletons: usize,
+}
+
+impl SccMmap {
+    pub fn component(&self, dense_id: u32) -> u32 {
+        let component_ids: &[u32] = util::mmap_as_u32_slice(&self.component_ids);
+        component_ids[dense_id as usize]
+    }
+
+    /// True when there's certainly no path from start to goal, false if there might be one
+    pub fn proves_unreachable(&self, start: u32, goal: u32) -> bool {
+        let (from, to) = (self.component(start), self.component(goal));
+        if from == to {
+            return false;
+        }
+        // links only go to higher components
+        if from > to || self.dag.get(from).is_empty() || self.dag.get_reverse(to).is_empty() {
+            return true;
+        }
+
+        // small dfs through the condensation, only components between the two can be on a route
+        let mut visited: FxHashSet<u32> = FxHashSet::default();
+        let mut stack = vec![from];
+        while let Some(component) = stack.pop() {
+            // dag edges are sorted
+            for &next in self
+                .dag
+                .get(component)
+                .iter()
+                .take_while(|&&next| next <= to)
+            {
+                if next == to {
+                    return false;
+                }
+                if visited.insert(next) {
+                    if visited.len() > DAG_SEARCH_LIMIT {
+                        return false;
+                    }
+                    stack.push(next);
+                }
+            }
+        }
+        true
+    }
+
+    pub fn stats(&self) -> SccStats {
+        let num_components = self.dag.num_nodes();
+        let mut sizes = vec![0usize; num_components];
+        for &component in util::mmap_as_u32_slice(&self.component_ids) {
+            sizes[component as usize] += 1;
+        }
+        let (giant_component, giant_size) = sizes
+            .iter()
+            .enumerate()
+            .max_by_key(|&(_, &size)| size)
+            .map_or((0, 0), |(component, &size)| (component as u32, size));
+
+        SccStats {
+            num_components,
+            giant_component,
+            giant_size,
+            num_singletons: sizes.iter().filter(|&&size| size == 1).count(),
+        }
+    }
+}
+
+pub fn build_and_save_scc_mmap(data: &DataDir) -> anyhow::Result<()> {
+    let csr_graph = load_csr_graph_mmap(data)?;
+
+    println!("finding strongly connected components");
+    let (component_ids, num_components) = strongly_connected_components(&csr_graph);
+    println!("building condensation dag of {} components", num_components);
+    let dag = condensation(&csr_graph, &component_ids, num_components);
+
+    util::write_u32_vec_to_file(&component_ids, data, "scc/component_ids.bin")?;
+    util::write_u32_vec_to_file(&dag.offsets, data, "scc/dag/offsets.bin")?;
+    util::write_u32_vec_to_file(&dag.edges, data, "scc/dag/edges.bin")?;
+    util::write_u32_vec_to_file(&dag.reverse_offsets, data, "scc/dag/reverse_offsets.bin")?;
+    util::write_u32_vec_to_file(&dag.reverse_edges, data, "scc/dag/reverse_edges.bin")?;
+
+    Ok(())
+}
+
+pub fn load_scc_mmap(data: &DataDir) -> anyhow::Result<SccMmap> {
+    let component_ids: MmapView = util::mmap_file::<u32>(data, "scc/component_ids.bin")?;
+    let dag = CsrGraphMmap {
+        offsets: util::mmap_file::<u32>(data, "scc/dag/offsets.bin")?,
+        reverse_offsets: util::mmap_file::<u32>(data, "scc/dag/reverse_offsets.bin")?,
+        edges_mmap: util::mmap_file::<u32>(data, "scc/dag/edges.bin")?,
+        reverse_edges_mmap: util::mmap_file::<u32>(data, "scc/dag/reverse_edges.bin")?,
+    };
+    check_csr_mmap(&dag)?;
+    Ok(SccMmap { component_ids, dag })
+}
+
+/// Print the component stats from the CLI
+pub fn print_scc_stats(data: &DataDir) -> anyhow::Result<()> {
+    let scc = load_scc_mmap(data)?;
+    let stats = scc.stats();
+    let num_nodes = util::mmap_as_u32_slice(&scc.component_ids).len();
+
+    println!("Strongly connected components: {}", stats.num_components);
+    println!(
+        "Giant component: {} pages ({:.1}% of {}), id {}",
+        stats.giant_size,
+        stats.giant_size as f64 / num_nodes.max(1) as f64 * 100.0,
+        num_nodes,
+        stats.giant_component
+    );
+    println!("Singletons: {}", stats.num_singletons);
+    println!("Condensation dag edges: {}", scc.dag.num_edges());
+    Ok(())
+}
@@ -1,13 +1,14 @@
+use crate::paths::DataDir;
 use crate::util;
-use memmap2::Mmap;
+use crate::util::MmapView;
 use rustc_hash::FxHashMap;
 use std::cmp::Ordering;
 
 pub struct TitleToDenseIdMmap {
     // sorted so can perform binary search
-    pub titles: Mmap,    // Vec<u8> blob of characters?
-    pub offsets: Mmap,   // Vec<u32>
-    pub dense_ids: Mmap, // Vec<u32>
+    pub titles: MmapView,    // Vec<u8> blob of characters?
+    pub offsets: MmapView,   // Vec<u32>
+    pub dense_ids: MmapView, // Vec<u32>
 }
 
 impl TitleToDenseIdMmap {
@@ -45,9 +46,9 @@ impl TitleToDenseIdMmap {
     }
 }
 
-pub fn build_and_save_title_to_dense_id_mmap() -> anyhow::Result<()> {
+pub fn build_and_save_title_to_dense_id_mmap(data: &DataDir) -> anyhow::Result<()> {
     let title_to_dense_id: FxHashMap<String, u32> =
-        util::load_from_file("data/title_to_dense_id.bin")?;
+        util::load_from_file(&data.file("title_to_dense_id.bin"))?;
 
     // Step 1: collect (title, dense_id) pairs and sort by title
     let mut entries: Vec<(&String, &u32)> = title_to_dense_id.iter().collect();
@@ -65,17 +66,19 @@ pub fn build_and_save_title_to_dense_id_mmap() -> anyhow::Result<()> {
     }
 
     // Step 3: save
-    util::write_u8_vec_to_file(&titles_blob, "data/title_to_dense_id/titles.bin")?;
-    util::write_u32_vec_to_file(&offsets, "data/title_to_dense_id/offsets.bin")?;
-    util::write_u32_vec_to_file(&dense_ids, "data/title_to_dense_id/dense_ids.bin")?;
+    util::write_u8_vec_to_file(&titles_blob, data, "title_to_dense_id/titles.bin")?;
+    util::write_u32_vec_to_file(&offsets, data, "title_to_dense_id/offsets.bin")?;
+    util::write_u32_vec_to_file(&dense_ids, data, "title_to_dense_id/dense_ids.bin")?;
 
     Ok(())
 }
 
-pub fn load_title_to_dense_id_mmap() -> anyhow::Result<TitleToDenseIdMmap> {
-    let titles = util::mmap_file("data/title_to_dense_id/titles.bin")?;
-    let offsets = util::mmap_file("data/title_to_dense_id/offsets.bin")?;
-    let dense_ids = util::mmap_file("data/title_to_dense_id/dense_ids.bin")?;
+pub fn load_title_to_dense_id_mmap(data: &DataDir) -> anyhow::Result<TitleToDenseIdMmap> {
+    let titles = util::mmap_file::<u8>(data, "title_to_dense_id/titles.bin")?;
+    let offsets = util::mmap_file::<u32>(data, "title_to_dense_id/offsets.bin")?;
+    let dense_ids = util::mmap_file::<u32>(data, "title_to_dense_id/dense_ids.bin")?;
+    // no end offset, the last title runs to the end of the blob
+    offsets.expect_count(dense_ids.header.count)?;
 
     Ok(TitleToDenseIdMmap {
         titles,
@@ -4,6 +4,8 @@ use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::search::LimitHit;
+
 // --- User Models ---
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -85,6 +87,57 @@ pub struct PathRequest {
     pub end_id: Option<u32>,
     #[serde(default)]
     pub output_as_ids: bool,
+    // which slice of the shortest paths to return, total count is always exact
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub format: PathFormat,
+    // pages the paths must not go through
+    #[serde(default)]
+    pub avoid: Vec<PageRef>,
+    // pages the paths must go through, in this order. the segments are joined before
+    // redirects are resolved (WaypointPaths::paths), so a waypoint reached by a redirect shows it
+    #[serde(default)]
+    pub via: Vec<PageRef>,
+    // leave out the most linked pages
+    #[serde(default)]
+    pub hubs: Option<HubFilter>,
+    // the k shortest loopless paths instead of every shortest path, lengths can differ
+    #[serde(default)]
+    pub k: Option<usize>,
+    // which of the server's wikis to search, its default one when missing
+    #[serde(default)]
+    pub wiki: Option<String>,
+}
+
+/// Which pages count as hubs, by in or out degree
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HubFilter {
+    // more links in or out than this
+    MaxDegree(u32),
+    // above this degree percentile, e.g. 99.9
+    Percentile(f64),
+}
+
+/// A page given as a title or an original id
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum PageRef {
+    Id(u32),
+    Title(String),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathFormat {
+    // every shortest path as a list of nodes
+    #[default]
+    Paths,
+    // the shortest-path subgraph as layers plus edges between adjacent layers
+    Dag,
 }
 
 #[derive(Debug, Serialize)]
@@ -94,14 +147,105 @@ pub enum PathNode {
     Id(u32),
 }
 
+#[derive(Debug, Serialize)]
+pub struct DagEdgeResponse {
+    // positions in layers[i] and layers[i + 1]
+    pub from: usize,
+    pub to: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<PathNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DagResponse {
+    pub layers: Vec<Vec<PathNode>>,
+    // edges[i] connects layers[i] to layers[i + 1]
+    pub edges: Vec<Vec<DagEdgeResponse>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchStatus {
+    Found,
+    // the whole reachable graph was searched
+    NoPath,
+    // stopped early, a path might still exist
+    BudgetExhausted,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HubReport {
+    pub num_excluded: usize,
+    // lowest in/out degree among the excluded hubs
+    pub degree_threshold: u32,
+    // excluded hubs that the unconstrained shortest paths go through
+    pub excluded_on_shortest_paths: Vec<PathNode>,
+    // none when there's no path even with the hubs
+    pub unconstrained_path_length: Option<u32>,
+    // pages the path has on top of the unconstrained one
+    pub extra_length: Option<u32>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct PathResponse {
+    pub status: SearchStatus,
+    // which limit stopped the search when budget_exhausted,
+    // or stopped a k shortest search before it found k paths
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<LimitHit>,
     pub elapsed_s: f64,
-    pub paths: Vec<Vec<PathNode>>,
+    pub total_paths: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paths: Option<Vec<Vec<PathNode>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dag: Option<DagResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hubs: Option<HubReport>,
     pub leaderboard_longest_rank: Option<u32>,
     pub leaderboard_most_rank: Option<u32>,
 }
 
+/// One start/end pair of a batch search, each given like in `PathRequest`
+#[derive(Debug, Deserialize)]
+pub struct BatchPair {
+    #[serde(default)]
+    pub start: Option<String>,
+    #[serde(default)]
+    pub start_id: Option<u32>,
+    #[serde(default)]
+    pub end: Option<String>,
+    #[serde(default)]
+    pub end_id: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchSearchRequest {
+    pub pairs: Vec<BatchPair>,
+    #[serde(default)]
+    pub output_as_ids: bool,
+    // paths returned per pair
+    #[serde(default)]
+    pub limit: Option<usize>,
+    // every pair is searched on the same wiki
+    #[serde(default)]
+    pub wiki: Option<String>,
+}
+
+/// The search result for one pair, or why it couldn't be searched
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum BatchSearchResult {
+    Done(PathResponse),
+    Failed { error: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchSearchResponse {
+    pub elapsed_s: f64,
+    // same order as the pairs in the request
+    pub results: Vec<BatchSearchResult>,
+}
+
 #[derive(Deserialize)]
 pub struct ChangeUsernameRequest {
     pub username: String,
@@ -0,0 +1,108 @@
+// src/paths.rs
+// where the sql dumps are read from and where the built files go, so several wikis can sit side by side
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::builders::Manifest;
+use crate::bundle::Bundle;
+
+pub const DEFAULT_DATA_DIR: &str = "data";
+pub const DEFAULT_DUMPS_DIR: &str = "../sql_files";
+pub const DEFAULT_WIKI: &str = "enwiki";
+
+/// Folder with one wiki's built files, every builder and loader takes one.
+/// Can also be a bundle of the mmap files, which only the loaders can read.
+#[derive(Debug, Clone)]
+pub struct DataDir {
+    path: PathBuf,
+    bundle: Option<Arc<Bundle>>,
+    // read from manifest.json once, Manifest::save keeps it up to date during a build
+    dataset_id: Arc<AtomicU64>,
+}
+
+impl DataDir {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        DataDir::with_bundle(dir.into(), None)
+    }
+
+    fn with_bundle(path: PathBuf, bundle: Option<Arc<Bundle>>) -> Self {
+        let data = DataDir {
+            path,
+            bundle,
+            dataset_id: Arc::default(),
+        };
+        if let Ok(Some(manifest)) = Manifest::load(&data) {
+            data.set_dataset_id(manifest.dataset_id());
+        }
+        data
+    }
+
+    /// A data dir, or a bundle when the path is a file
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let bundle = if path.is_file() {
+            Some(Arc::new(Bundle::open(&path)?))
+        } else {
+            None
+        };
+        Ok(DataDir::with_bundle(path, bundle))
+    }
+
+    /// Path of a file in the folder, like "csr/edges.bin"
+    pub fn file(&self, name: &str) -> String {
+        self.path.join(name).to_string_lossy().into_owned()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn bundle(&self) -> Option<&Bundle> {
+        self.bundle.as_deref()
+    }
+
+    /// Which build the files are from, see `Manifest::dataset_id`.
+    /// 0 when the folder wasn't built with `build`, then files aren't checked against it
+    pub fn dataset_id(&self) -> u64 {
+        self.dataset_id.load(Ordering::Relaxed)
+    }
+
+    pub fn set_dataset_id(&self, dataset_id: u64) {
+        self.dataset_id.store(dataset_id, Ordering::Relaxed);
+    }
+}
+
+impl Default for DataDir {
+    fn default() -> Self {
+        DataDir::new(DEFAULT_DATA_DIR)
+    }
+}
+
+/// The sql dumps of one wiki, named like on dumps.wikimedia.org
+#[derive(Debug, Clone)]
+pub struct Dumps {
+    pub dir: PathBuf,
+    // dewiki, frwiki, simplewiki...
+    pub wiki: String,
+}
+
+impl Dumps {
+    /// e.g. "../sql_files/dewiki-latest-pagelinks.sql.gz"
+    pub fn table(&self, table: &str) -> String {
+        self.dir
+            .join(format!("{}-latest-{}.sql.gz", self.wiki, table))
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl Default for Dumps {
+    fn default() -> Self {
+        Dumps {
+            dir: PathBuf::from(DEFAULT_DUMPS_DIR),
+            wiki: DEFAULT_WIKI.to_string(),
+        }
+    }
+}
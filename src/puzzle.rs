@@ -0,0 +1,283 @@
+// src/puzzle.rs
+// picks start/goal pairs for puzzles by how far apart they are and how many shortest paths they have
+
+use std::io::{self, Write};
+
+use chrono::{Datelike, NaiveDate};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::graph::*;
+use crate::mmap_structs::*;
+use crate::paths::DataDir;
+use crate::search::{bi_bfs_csr, bi_bfs_csr_depth_only, LimitHit, SearchLimits, SearchOutcome};
+use crate::search_context::SearchContext;
+
+// random pairs looked at for one puzzle before giving up
+pub const PUZZLE_TRIES: usize = 5000;
+
+// titles that are indexes of other pages rather than topics, too easy to get through
+const SKIPPED_PREFIXES: &[&str] = &[
+    "List_of_",
+    "Lists_of_",
+    "Index_of_",
+    "Outline_of_",
+    "Timeline_of_",
+    "Glossary_of_",
+    "Bibliography_of_",
+];
+const SKIPPED_SUFFIXES: &[&str] = &[
+    "_(disambiguation)",
+    "_(surname)",
+    "_(given_name)",
+    "_(name)",
+];
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+}
+
+/// Which pairs make a puzzle, both ranges inclusive
+#[derive(Debug, Clone, Copy)]
+pub struct PuzzleBand {
+    pub min_distance: u8,
+    pub max_distance: u8,
+    // number of shortest paths
+    pub min_paths: u64,
+    pub max_paths: u64,
+}
+
+impl Difficulty {
+    pub fn band(self) -> PuzzleBand {
+        match self {
+            // close, with plenty of ways there
+            Difficulty::Easy => PuzzleBand {
+                min_distance: 2,
+                max_distance: 3,
+                min_paths: 5,
+                max_paths: u64::MAX,
+            },
+            Difficulty::Medium => PuzzleBand {
+                min_distance: 3,
+                max_distance: 4,
+                min_paths: 1,
+                max_paths: u64::MAX,
+            },
+            // far, and only a couple of ways to get there in the fewest clicks
+            Difficulty::Hard => PuzzleBand {
+                min_distance: 5,
+                max_distance: 8,
+                min_paths: 1,
+                max_paths: 2,
+            },
+        }
+    }
+}
+
+/// Same seed for everyone on the same day, different for each difficulty
+pub fn daily_seed(date: NaiveDate, difficulty: Difficulty) -> u64 {
+    date.num_days_from_ce() as u64 * 4 + difficulty as u64
+}
+
+/// False for disambiguations, lists, bare numbers and years and the like
+pub fn is_puzzle_page(title: &str) -> bool {
+    !(SKIPPED_PREFIXES
+        .iter()
+        .any(|prefix| title.starts_with(prefix))
+        || SKIPPED_SUFFIXES
+            .iter()
+            .any(|suffix| title.ends_with(suffix))
+        || title.chars().all(|c| !c.is_alphabetic()))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Puzzle {
+    pub start: u32,
+    pub goal: u32,
+    pub distance: u8,
+    pub num_paths: u64,
+}
+
+/// Puzzle with titles, for printing or sending
+#[derive(Debug, Clone, Serialize)]
+pub struct PuzzleOutput {
+    pub start: String,
+    pub goal: String,
+    pub distance: u8,
+    pub num_paths: u64,
+}
+
+pub struct PuzzleGenerator<'a, G> {
+    pub graph: &'a G,
+    pub dense_id_to_title: &'a DenseIdToTitleMmap,
+    pub redirect_targets_dense: &'a RedirectTargetsDenseMmap,
+    // only used to skip pairs early, the puzzles are the same without them
+    pub landmarks: Option<&'a LandmarksMmap>,
+}
+
+impl<G: CsrGraphTrait> PuzzleGenerator<'_, G> {
+    /// Try up to `max_tries` random pairs for one in the band. The distance is screened with the
+    /// depth only search, and only pairs at the right distance get the full search to count paths.
+    /// Pairs that hit `max_nodes` are skipped, so with the same rng this always picks the same
+    /// puzzle, unless the deadline or cancel flag in `limits` stops it (then it's none).
+    pub fn generate(
+        &self,
+        ctx: &mut SearchContext,
+        band: &PuzzleBand,
+        rng: &mut impl Rng,
+        max_tries: usize,
+        limits: &SearchLimits,
+    ) -> Option<Puzzle> {
+        let num_nodes = self.graph.num_nodes();
+        // anything farther than the band isn't worth searching to the end
+        let limits = SearchLimits {
+            max_depth: band.max_distance,
+            ..limits.clone()
+        };
+
+        for _ in 0..max_tries {
+            let start = rng.random_range(0..num_nodes) as u32;
+            let goal = rng.random_range(0..num_nodes) as u32;
+            if start == goal || !self.is_candidate(start) || !self.is_candidate(goal) {
+                continue;
+            }
+
+            let bounds = match self.landmarks.map(|l| l.estimate_distance(start, goal)) {
+                Some(None) => continue,
+                Some(Some(bounds)) => {
+                    let too_far = bounds.lower > band.max_distance;
+                    let too_close = bounds.upper.is_some_and(|upper| upper < band.min_distance);
+                    if too_far || too_close {
+                        continue;
+                    }
+                    Some(bounds)
+                }
+                None => None,
+            };
+
+            let distance =
+                match bi_bfs_csr_depth_only(self.graph, ctx, start, goal, &limits, bounds) {
+                    SearchOutcome::Found(distance) => distance,
+                    SearchOutcome::NoPath
+                    | SearchOutcome::Exhausted(LimitHit::MaxDepth | LimitHit::MaxNodes) => continue,
+                    SearchOutcome::Exhausted(LimitHit::Deadline | LimitHit::Cancelled) => {
+                        return None
+                    }
+                };
+            if distance < band.min_distance {
+                continue;
+            }
+
+            let mut node_count = 0;
+            let num_paths = match bi_bfs_csr(self.graph, ctx, start, goal, &limits, &mut node_count)
+            {
+                SearchOutcome::Found(dag) => dag.num_paths(),
+                SearchOutcome::NoPath
+                | SearchOutcome::Exhausted(LimitHit::MaxDepth | LimitHit::MaxNodes) => continue,
+                SearchOutcome::Exhausted(LimitHit::Deadline | LimitHit::Cancelled) => return None,
+            };
+            if (band.min_paths..=band.max_paths).contains(&num_paths) {
+                return Some(Puzzle {
+                    start,
+                    goal,
+                    distance,
+                    num_paths,
+                });
+            }
+        }
+        None
+    }
+
+    fn is_candidate(&self, dense_id: u32) -> bool {
+        // redirects aren't pages of their own
+        self.redirect_targets_dense.get(dense_id) == u32::MAX
+            && is_puzzle_page(self.dense_id_to_title.get(dense_id))
+    }
+
+    pub fn output(&self, puzzle: &Puzzle) -> PuzzleOutput {
+        PuzzleOutput {
+            start: self.dense_id_to_title.get(puzzle.start).to_string(),
+            goal: self.dense_id_to_title.get(puzzle.goal).to_string(),
+            distance: puzzle.distance,
+            num_paths: puzzle.num_paths,
+        }
+    }
+}
+
+/// Print `count` puzzles as json, or csv with `csv`. `seed` makes the list repeatable.
+pub fn run_puzzles_cli(
+    data: &DataDir,
+    count: usize,
+    band: &PuzzleBand,
+    seed: Option<u64>,
+    csv: bool,
+) -> anyhow::Result<()> {
+    let graph = load_csr_graph_mmap(data)?;
+    let dense_id_to_title = load_dense_id_to_title_mmap(data)?;
+    let redirect_targets_dense = load_redirect_targets_dense_mmap(data)?;
+    let landmarks = load_landmarks_mmap(data).ok();
+    let generator = PuzzleGenerator {
+        graph: &graph,
+        dense_id_to_title: &dense_id_to_title,
+        redirect_targets_dense: &redirect_targets_dense,
+        landmarks: landmarks.as_ref(),
+    };
+
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_rng(&mut rand::rng()),
+    };
+    let mut ctx = SearchContext::new(graph.num_nodes());
+    let mut puzzles = Vec::with_capacity(count);
+    for _ in 0..count {
+        match generator.generate(
+            &mut ctx,
+            band,
+            &mut rng,
+            PUZZLE_TRIES,
+            &SearchLimits::default(),
+        ) {
+            Some(puzzle) => puzzles.push(generator.output(&puzzle)),
+            None => {
+                eprintln!("no pair in {:?} after {} tries", band, PUZZLE_TRIES);
+                break;
+            }
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    if csv {
+        writeln!(out, "start,goal,distance,num_paths")?;
+        for puzzle in &puzzles {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                csv_field(&puzzle.start),
+                csv_field(&puzzle.goal),
+                puzzle.distance,
+                puzzle.num_paths
+            )?;
+        }
+    } else {
+        serde_json::to_writer_pretty(&mut out, &puzzles)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// quoted when it has a comma or a quote in it
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
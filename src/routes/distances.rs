@@ -0,0 +1,379 @@
+// src/routes/distances.rs
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::search::{queue_full_response, resolve_page, unknown_wiki_response, CancelOnDrop};
+use crate::mmap_structs::DistanceBounds;
+use crate::models::{PageRef, PathNode};
+use crate::search::{self, SearchLimits, SearchOutcome};
+use crate::search_executor::QueueFull;
+use crate::state::AppState;
+use crate::util::json_response;
+
+// farthest pages returned when no sample size is given, and the most a request can ask for
+const DEFAULT_SAMPLE: usize = 20;
+const MAX_SAMPLE: usize = 1000;
+// most targets in one multi-target request
+const MAX_TARGETS: usize = 1000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    // pages reachable from the page
+    #[default]
+    Forward,
+    // pages that can reach the page
+    Backward,
+}
+
+#[derive(Deserialize)]
+pub struct DistancesQuery {
+    page: Option<String>,
+    page_id: Option<u32>,
+    #[serde(default)]
+    direction: Direction,
+    sample: Option<usize>,
+    wiki: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DistancesResponse {
+    page: String,
+    direction: Direction,
+    // counts[d] = pages at distance d
+    counts: Vec<u32>,
+    reachable: u64,
+    eccentricity: usize,
+    // some of the pages at the largest distance
+    farthest: Vec<String>,
+    elapsed_s: f64,
+}
+
+/// GET /distances?page=Kevin_Bacon&direction=forward, pages per distance from one page
+pub async fn distances_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DistancesQuery>,
+) -> impl IntoResponse {
+    let Some(dataset) = state.dataset(params.wiki.as_deref()).cloned() else {
+        return unknown_wiki_response(&state, params.wiki.as_deref());
+    };
+    let page = match page_ref(params.page, params.page_id) {
+        Some(page) => page,
+        None => {
+            return json_response(
+                json!({"error": "Exactly one of page or page_id must be provided"}),
+                StatusCode::BAD_REQUEST,
+            )
+        }
+    };
+    let Some(source) = resolve_page(&dataset, &page) else {
+        return json_response(json!({"error": "Page not found"}), StatusCode::NOT_FOUND);
+    };
+
+    let sample = params.sample.unwrap_or(DEFAULT_SAMPLE).min(MAX_SAMPLE);
+    let backwards = params.direction == Direction::Backward;
+    let cancel = CancelOnDrop::new();
+    // a full bfs expands every reachable page, so only the time limit applies, not the node budget
+    let limits = SearchLimits {
+        deadline: Some(Instant::now() + Duration::from_millis(state.env.search_timeout_ms)),
+        cancel: Some(cancel.0.clone()),
+        ..SearchLimits::default()
+    };
+    let search_dataset = dataset.clone();
+    let submitted = state.search_executor.try_submit(move || {
+        let start = Instant::now();
+        let mut node_count = 0;
+        let outcome = search::distance_histogram(
+            &search_dataset.csr_graph,
+            &mut search_dataset.search_contexts.get(),
+            source,
+            backwards,
+            sample,
+            &limits,
+            &mut node_count,
+        );
+        (outcome, start.elapsed().as_secs_f64())
+    });
+    let receiver = match submitted {
+        Ok(receiver) => receiver,
+        Err(QueueFull) => return queue_full_response(),
+    };
+
+    let (outcome, elapsed_s) = match receiver.await {
+        Ok(output) => output,
+        Err(_) => {
+            tracing::error!("distance histogram failed for {}", source);
+            return json_response(
+                json!({"error": "Search failed"}),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+    drop(cancel);
+
+    let histogram = match outcome {
+        SearchOutcome::Found(histogram) => histogram,
+        SearchOutcome::NoPath | SearchOutcome::Exhausted(_) => {
+            return json_response(
+                json!({"error": "Search took too long and was stopped"}),
+                StatusCode::SERVICE_UNAVAILABLE,
+            )
+        }
+    };
+
+    let response = DistancesResponse {
+        page: dataset.dense_id_to_title.get(source).to_string(),
+        direction: params.direction,
+        reachable: histogram.reachable(),
+        eccentricity: histogram.eccentricity(),
+        farthest: histogram
+            .farthest
+            .iter()
+            .map(|&id| dataset.dense_id_to_title.get(id).to_string())
+            .collect(),
+        counts: histogram.counts,
+        elapsed_s,
+    };
+
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .header("Cache-Control", "public, max-age=31536000, immutable") // ~1 year
+        .body(Body::from(serde_json::to_vec(&response).unwrap()))
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+pub struct TargetsRequest {
+    page: PageRef,
+    targets: Vec<PageRef>,
+    // backward gives the distance from each target to the page
+    #[serde(default)]
+    direction: Direction,
+    #[serde(default)]
+    output_as_ids: bool,
+    #[serde(default)]
+    wiki: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TargetResult {
+    // none for targets that don't exist or can't be reached
+    distance: Option<u8>,
+    // one shortest path, in link order
+    path: Option<Vec<PathNode>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TargetsResponse {
+    page: String,
+    direction: Direction,
+    // same order as the targets in the request
+    results: Vec<TargetResult>,
+    elapsed_s: f64,
+}
+
+/// POST /distances/targets, the distance and a shortest path from one page to each of the
+/// targets (or from each target to it), from one bfs that stops once all targets are reached
+pub async fn targets_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<TargetsRequest>,
+) -> impl IntoResponse {
+    let Some(dataset) = state.dataset(req.wiki.as_deref()).cloned() else {
+        return unknown_wiki_response(&state, req.wiki.as_deref());
+    };
+    if req.targets.is_empty() || req.targets.len() > MAX_TARGETS {
+        return json_response(
+            json!({"error": format!("Between 1 and {} targets", MAX_TARGETS)}),
+            StatusCode::BAD_REQUEST,
+        );
+    }
+    let Some(source) = resolve_page(&dataset, &req.page) else {
+        return json_response(json!({"error": "Page not found"}), StatusCode::NOT_FOUND);
+    };
+    // targets that don't exist get an error of their own instead of failing the request
+    let resolved: Vec<Option<u32>> = req
+        .targets
+        .iter()
+        .map(|target| resolve_page(&dataset, target))
+        .collect();
+    let targets: Vec<u32> = resolved.iter().flatten().copied().collect();
+
+    let backwards = req.direction == Direction::Backward;
+    let cancel = CancelOnDrop::new();
+    // stops early, but can still expand most of the graph for a far target, so it gets the same
+    // budget as a full distances bfs
+    let limits = SearchLimits {
+        deadline: Some(Instant::now() + Duration::from_millis(state.env.search_timeout_ms)),
+        cancel: Some(cancel.0.clone()),
+        ..SearchLimits::default()
+    };
+    let search_dataset = dataset.clone();
+    let submitted = state.search_executor.try_submit(move || {
+        let start = Instant::now();
+        let mut node_count = 0;
+        let outcome = search::multi_target_distances(
+            &search_dataset.csr_graph,
+            &mut search_dataset.search_contexts.get(),
+            source,
+            &targets,
+            backwards,
+            &limits,
+            &mut node_count,
+        );
+        (outcome, start.elapsed().as_secs_f64())
+    });
+    let receiver = match submitted {
+        Ok(receiver) => receiver,
+        Err(QueueFull) => return queue_full_response(),
+    };
+
+    let (outcome, elapsed_s) = match receiver.await {
+        Ok(output) => output,
+        Err(_) => {
+            tracing::error!("multi target distances failed for {}", source);
+            return json_response(
+                json!({"error": "Search failed"}),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+    drop(cancel);
+
+    let distances = match outcome {
+        SearchOutcome::Found(distances) => distances,
+        SearchOutcome::NoPath | SearchOutcome::Exhausted(_) => {
+            return json_response(
+                json!({"error": "Search took too long and was stopped"}),
+                StatusCode::SERVICE_UNAVAILABLE,
+            )
+        }
+    };
+
+    let to_node = |dense_id: u32| {
+        if req.output_as_ids {
+            PathNode::Id(dataset.dense_id_to_orig.get(dense_id))
+        } else {
+            PathNode::Title(dataset.dense_id_to_title.get(dense_id).to_string())
+        }
+    };
+    let mut distances = distances.into_iter();
+    let results = resolved
+        .iter()
+        .map(|target| {
+            if target.is_none() {
+                return TargetResult {
+                    distance: None,
+                    path: None,
+                    error: Some("Page not found".to_string()),
+                };
+            }
+            match distances.next().flatten() {
+                Some(mut target_distance) => {
+                    search::resolve_redirects(&mut target_distance.path, &dataset.redirects_passed);
+                    TargetResult {
+                        distance: Some(target_distance.distance),
+                        path: Some(target_distance.path.into_iter().map(to_node).collect()),
+                        error: None,
+                    }
+                }
+                None => TargetResult {
+                    distance: None,
+                    path: None,
+                    error: None,
+                },
+            }
+        })
+        .collect();
+
+    let response = TargetsResponse {
+        page: dataset.dense_id_to_title.get(source).to_string(),
+        direction: req.direction,
+        results,
+        elapsed_s,
+    };
+
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .header("Cache-Control", "no-store")
+        .body(Body::from(serde_json::to_vec(&response).unwrap()))
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+pub struct EstimateQuery {
+    start: Option<String>,
+    start_id: Option<u32>,
+    end: Option<String>,
+    end_id: Option<u32>,
+    wiki: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct EstimateResponse {
+    start: String,
+    end: String,
+    // none when the landmarks prove there's no path
+    bounds: Option<DistanceBounds>,
+}
+
+/// GET /distances/estimate?start=A&end=B, landmark bounds on the distance without a search
+pub async fn estimate_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<EstimateQuery>,
+) -> impl IntoResponse {
+    let Some(dataset) = state.dataset(params.wiki.as_deref()).cloned() else {
+        return unknown_wiki_response(&state, params.wiki.as_deref());
+    };
+    let Some(landmarks) = &dataset.landmarks else {
+        return json_response(
+            json!({"error": "Distance estimates aren't available"}),
+            StatusCode::SERVICE_UNAVAILABLE,
+        );
+    };
+    let (start, end) = match (
+        page_ref(params.start, params.start_id),
+        page_ref(params.end, params.end_id),
+    ) {
+        (Some(start), Some(end)) => (start, end),
+        _ => {
+            return json_response(
+                json!({"error": "Exactly one of start or start_id and one of end or end_id must be provided"}),
+                StatusCode::BAD_REQUEST,
+            )
+        }
+    };
+    let (Some(start), Some(end)) = (resolve_page(&dataset, &start), resolve_page(&dataset, &end))
+    else {
+        return json_response(json!({"error": "Page not found"}), StatusCode::NOT_FOUND);
+    };
+
+    let response = EstimateResponse {
+        start: dataset.dense_id_to_title.get(start).to_string(),
+        end: dataset.dense_id_to_title.get(end).to_string(),
+        bounds: landmarks.estimate_distance(start, end),
+    };
+
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .header("Cache-Control", "public, max-age=31536000, immutable") // ~1 year
+        .body(Body::from(serde_json::to_vec(&response).unwrap()))
+        .unwrap()
+}
+
+fn page_ref(title: Option<String>, orig_id: Option<u32>) -> Option<PageRef> {
+    match (title, orig_id) {
+        (Some(title), None) => Some(PageRef::Title(title)),
+        (None, Some(orig_id)) => Some(PageRef::Id(orig_id)),
+        _ => None,
+    }
+}
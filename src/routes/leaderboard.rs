@@ -13,6 +13,8 @@ use std::{
     sync::Arc,
 };
 
+use super::search::unknown_wiki_response;
+use crate::leaderboard::{leaderboard_key, username_field};
 use crate::state::AppState; // assumes redis + sqlite + dense_to_title
 
 #[derive(Serialize)]
@@ -34,6 +36,7 @@ pub struct LeaderboardResponse {
 pub struct LeaderboardQuery {
     offset: Option<usize>, // default 0
     limit: Option<usize>,  // default 50
+    wiki: Option<String>,  // default: the server's default wiki
 }
 
 pub async fn get_leaderboard(
@@ -42,7 +45,10 @@ pub async fn get_leaderboard(
     Query(params): Query<LeaderboardQuery>,
     headers: HeaderMap,
 ) -> impl IntoResponse {
-    let leaderboard_key = format!("leaderboard:{}", leaderboard_type);
+    let Some(dataset) = state.dataset(params.wiki.as_deref()).cloned() else {
+        return unknown_wiki_response(&state, params.wiki.as_deref());
+    };
+    let leaderboard_key = leaderboard_key(&dataset.wiki, &leaderboard_type);
     let username_hash_key = "leaderboard:username";
 
     let offset = params.offset.unwrap_or(0);
@@ -63,7 +69,7 @@ pub async fn get_leaderboard(
     // 2️⃣ Build Redis hash keys to fetch usernames
     let hash_keys: Vec<String> = entries
         .iter()
-        .map(|(path, _)| format!("{}|{}", path, leaderboard_type))
+        .map(|(path, _)| username_field(path, &dataset.wiki, &leaderboard_type))
         .collect();
 
     // 3️⃣ Fetch all usernames in a single HMGET
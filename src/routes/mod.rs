@@ -15,17 +15,27 @@ use tower_http::cors::CorsLayer;
 
 // Import your handlers
 mod auth;
+mod distances;
 mod leaderboard;
+mod puzzle;
 mod search;
 mod user;
 
 use auth::{google_auth_login_handler, logout_handler, me_handler};
-use search::search_handler;
+use distances::{distances_handler, estimate_handler, targets_handler};
+use puzzle::{daily_puzzle_handler, random_puzzle_handler};
+use search::{search_batch_handler, search_handler};
 use user::change_username_handler;
 
 pub fn create_router(state: Arc<AppState>, cors: CorsLayer) -> Router {
     Router::new()
         .route("/search", post(search_handler))
+        .route("/search/batch", post(search_batch_handler))
+        .route("/distances", get(distances_handler))
+        .route("/distances/estimate", get(estimate_handler))
+        .route("/distances/targets", post(targets_handler))
+        .route("/puzzle/random", get(random_puzzle_handler))
+        .route("/puzzle/daily", get(daily_puzzle_handler))
         .route("/me", get(me_handler))
         .route("/auth/google", post(google_auth_login_handler))
         .route("/auth/logout", post(logout_handler))
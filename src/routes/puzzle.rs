@@ -0,0 +1,209 @@
+// src/routes/puzzle.rs
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::{NaiveDate, Timelike, Utc};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::search::{queue_full_response, unknown_wiki_response, CancelOnDrop};
+use crate::puzzle::{daily_seed, Difficulty, PuzzleGenerator, PuzzleOutput, PUZZLE_TRIES};
+use crate::search::SearchLimits;
+use crate::search_executor::QueueFull;
+use crate::state::{AppState, Dataset};
+use crate::util::json_response;
+
+#[derive(Deserialize)]
+pub struct PuzzleQuery {
+    #[serde(default)]
+    difficulty: Difficulty,
+    wiki: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PuzzleResponse {
+    difficulty: Difficulty,
+    // only for the daily puzzle, yyyy-mm-dd
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    #[serde(flatten)]
+    puzzle: PuzzleOutput,
+}
+
+/// GET /puzzle/random?difficulty=hard, a new pair every time
+pub async fn random_puzzle_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<PuzzleQuery>,
+) -> impl IntoResponse {
+    puzzle_response(state, params, None).await
+}
+
+/// GET /puzzle/daily?difficulty=hard, the same pair for everyone until midnight utc
+pub async fn daily_puzzle_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<PuzzleQuery>,
+) -> impl IntoResponse {
+    let today = Utc::now().date_naive();
+    puzzle_response(state, params, Some(today)).await
+}
+
+async fn puzzle_response(
+    state: Arc<AppState>,
+    params: PuzzleQuery,
+    date: Option<NaiveDate>,
+) -> Response {
+    let Some(dataset) = state.dataset(params.wiki.as_deref()).cloned() else {
+        return unknown_wiki_response(&state, params.wiki.as_deref());
+    };
+    let difficulty = params.difficulty;
+    let puzzle = match date {
+        Some(date) => daily_puzzle(&state, &dataset, difficulty, date).await,
+        None => random_puzzle(&state, &dataset, difficulty).await,
+    };
+    let puzzle = match puzzle {
+        Ok(Some(puzzle)) => puzzle,
+        // the daily one has no time limit, trying again gives the same answer
+        Ok(None) if date.is_some() => {
+            return json_response(
+                json!({"error": "Couldn't find a puzzle for today at this difficulty"}),
+                StatusCode::SERVICE_UNAVAILABLE,
+            )
+        }
+        Ok(None) => {
+            return json_response(
+                json!({"error": "Couldn't find a puzzle in time, try again"}),
+                StatusCode::SERVICE_UNAVAILABLE,
+            )
+        }
+        Err(response) => return response,
+    };
+
+    let cache_control = match date {
+        // until the next one
+        Some(_) => {
+            let seconds_left = 86_400 - Utc::now().num_seconds_from_midnight();
+            format!("public, max-age={}", seconds_left)
+        }
+        None => "no-store".to_string(),
+    };
+    let response = PuzzleResponse {
+        difficulty,
+        date: date.map(|date| date.format("%Y-%m-%d").to_string()),
+        puzzle,
+    };
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .header("Cache-Control", cache_control)
+        .body(Body::from(serde_json::to_vec(&response).unwrap()))
+        .unwrap()
+}
+
+/// A new pair within the request's time budget
+async fn random_puzzle(
+    state: &AppState,
+    dataset: &Arc<Dataset>,
+    difficulty: Difficulty,
+) -> Result<Option<PuzzleOutput>, Response> {
+    let cancel = CancelOnDrop::new();
+    let limits = SearchLimits {
+        deadline: Some(Instant::now() + Duration::from_millis(state.env.search_timeout_ms)),
+        cancel: Some(cancel.0.clone()),
+        ..SearchLimits::default()
+    };
+    generate_puzzle(state, dataset, difficulty, None, limits).await
+}
+
+/// Today's pair, generated once and kept until the day is over
+async fn daily_puzzle(
+    state: &Arc<AppState>,
+    dataset: &Arc<Dataset>,
+    difficulty: Difficulty,
+    date: NaiveDate,
+) -> Result<Option<PuzzleOutput>, Response> {
+    let cell = {
+        let mut daily_puzzles = state.daily_puzzles.lock().unwrap();
+        daily_puzzles.retain(|(_, day, _), _| *day >= date);
+        daily_puzzles
+            .entry((dataset.wiki.clone(), date, difficulty))
+            .or_default()
+            .clone()
+    };
+    if let Some(puzzle) = cell.get() {
+        return Ok(puzzle.clone());
+    }
+    // no deadline or node budget, a search cut short would skip a pair and the puzzle
+    // would depend on how busy the server was. only the first request pays for it.
+    // it fills the cell from its own task, so a client that leaves doesn't waste the search,
+    // and requests coming in meanwhile wait for that one instead of starting another
+    let seed = daily_seed(date, difficulty);
+    let (state, dataset) = (state.clone(), dataset.clone());
+    let generation = tokio::spawn(async move {
+        cell.get_or_try_init(|| {
+            generate_puzzle(
+                &state,
+                &dataset,
+                difficulty,
+                Some(seed),
+                SearchLimits::default(),
+            )
+        })
+        .await
+        .cloned()
+    });
+    match generation.await {
+        Ok(puzzle) => puzzle,
+        Err(e) => {
+            tracing::error!("daily puzzle task failed ({:?}): {}", difficulty, e);
+            Err(json_response(
+                json!({"error": "Puzzle generation failed"}),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+/// Run the generator on the search executor, seeded or from a random seed
+async fn generate_puzzle(
+    state: &AppState,
+    dataset: &Arc<Dataset>,
+    difficulty: Difficulty,
+    seed: Option<u64>,
+    limits: SearchLimits,
+) -> Result<Option<PuzzleOutput>, Response> {
+    let search_dataset = dataset.clone();
+    let submitted = state.search_executor.try_submit(move || {
+        let generator = PuzzleGenerator {
+            graph: &search_dataset.csr_graph,
+            dense_id_to_title: &search_dataset.dense_id_to_title,
+            redirect_targets_dense: &search_dataset.redirect_targets_dense,
+            landmarks: search_dataset.landmarks.as_ref(),
+        };
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+        generator
+            .generate(
+                &mut search_dataset.search_contexts.get(),
+                &difficulty.band(),
+                &mut rng,
+                PUZZLE_TRIES,
+                &limits,
+            )
+            .map(|puzzle| generator.output(&puzzle))
+    });
+    let receiver = submitted.map_err(|QueueFull| queue_full_response())?;
+    receiver.await.map_err(|_| {
+        tracing::error!("puzzle generation failed ({:?})", difficulty);
+        json_response(
+            json!({"error": "Puzzle generation failed"}),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })
+}
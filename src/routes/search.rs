@@ -1,11 +1,20 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 // src/routes/search.rs
-use crate::leaderboard::try_add_to_leaderboard;
-use crate::models::{PathNode, PathRequest, PathResponse};
+use crate::graph::CsrGraphTrait;
+use crate::k_shortest;
+use crate::leaderboard::{try_add_to_leaderboard, LeaderboardSearch};
+use crate::models::{
+    BatchSearchRequest, BatchSearchResponse, BatchSearchResult, DagEdgeResponse, DagResponse,
+    HubFilter, HubReport, PageRef, PathFormat, PathNode, PathRequest, PathResponse, SearchStatus,
+};
 use crate::search;
-use crate::state::AppState;
+use crate::search::{LayeredDag, LimitHit, SearchLimits, SearchOutcome, Searcher};
+use crate::search_executor::QueueFull;
+use crate::state::{AppState, Dataset};
 use crate::util::json_response;
 use axum::body::Body;
 use axum::http::StatusCode;
@@ -13,8 +22,387 @@ use axum::response::Response;
 use axum::{extract::State, response::IntoResponse, Extension, Json};
 use chrono::Utc;
 use serde_json::json;
+use tokio::sync::oneshot;
 use uuid::Uuid;
 
+// paths returned per request when no limit is given, and the most a request can ask for
+const DEFAULT_PATH_LIMIT: usize = 1000;
+const MAX_PATH_LIMIT: usize = 10_000;
+// seconds a client should wait when the search queue is full
+const RETRY_AFTER_S: u32 = 2;
+// longest path the api searches for (per segment when there are waypoints)
+const MAX_SEARCH_DEPTH: u8 = 50;
+// most avoided pages and waypoints a request can have
+const MAX_AVOID: usize = 100;
+const MAX_VIA: usize = 8;
+// most paths a k shortest search can ask for
+const MAX_K: usize = 100;
+// most pairs in one batch search, and how many of them run at once
+const MAX_BATCH_PAIRS: usize = 50;
+const MAX_BATCH_IN_FLIGHT: usize = 4;
+
+/// Tells the search to stop if the handler goes away (client disconnected) before it's done
+pub struct CancelOnDrop(pub Arc<AtomicBool>);
+
+impl CancelOnDrop {
+    pub fn new() -> Self {
+        CancelOnDrop(Arc::new(AtomicBool::new(false)))
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// What to search for, in dense ids
+struct SearchJob {
+    // start, the waypoints, then goal
+    stops: Vec<u32>,
+    // avoided pages
+    excluded: Vec<u32>,
+    // hubs to leave out on top of that, the search also runs with them to compare
+    hubs: Vec<u32>,
+    // k shortest loopless paths instead of all shortest paths
+    k: Option<usize>,
+    format: PathFormat,
+    offset: usize,
+    limit: usize,
+}
+
+/// How the search with hubs left out compares to the one with them, in dense ids
+struct HubComparison {
+    excluded_on_shortest_paths: Vec<u32>,
+    unconstrained_path_length: Option<u32>,
+}
+
+/// What a search job hands back to the handler, still in dense ids
+struct SearchOutput {
+    elapsed_s: f64,
+    node_count: u32,
+    status: SearchStatus,
+    limit: Option<LimitHit>,
+    total_paths: u64,
+    path_length: u32,
+    // one of these depending on the requested format
+    paths: Option<Vec<Vec<u32>>>,
+    layered: Option<LayeredDag>,
+    hub_comparison: Option<HubComparison>,
+}
+
+impl SearchOutput {
+    /// For a pair answered without a search
+    fn unsearched() -> Self {
+        SearchOutput {
+            elapsed_s: 0.0,
+            node_count: 0,
+            status: SearchStatus::NoPath,
+            limit: None,
+            total_paths: 0,
+            path_length: 0,
+            paths: Some(Vec::new()),
+            layered: None,
+            hub_comparison: None,
+        }
+    }
+}
+
+/// Runs on a search executor thread: the search itself plus the path enumeration,
+/// which can also be slow for pairs with a lot of shortest paths.
+fn run_search(dataset: &Dataset, job: &SearchJob, limits: &SearchLimits) -> SearchOutput {
+    if let Some(k) = job.k {
+        return run_k_shortest(dataset, job, k, limits);
+    }
+
+    let start_bfs = Instant::now();
+    let mut ctx = dataset.search_contexts.get();
+    let mut searcher = Searcher::new(&dataset.csr_graph, &mut ctx, limits);
+    let (start, via, goal) = (
+        job.stops[0],
+        &job.stops[1..job.stops.len() - 1],
+        job.stops[job.stops.len() - 1],
+    );
+    let mut search =
+        |excluded: &[u32]| search::bi_bfs_csr_via(&mut searcher, start, via, goal, excluded);
+
+    let mut outcome = search(&job.excluded);
+    let mut hub_comparison = None;
+    if !job.hubs.is_empty() {
+        if let SearchOutcome::Found(unconstrained) = &outcome {
+            let on_paths = unconstrained.nodes();
+            let excluded_on_shortest_paths: Vec<u32> = job
+                .hubs
+                .iter()
+                .copied()
+                .filter(|hub| on_paths.contains(hub))
+                .collect();
+            hub_comparison = Some(HubComparison {
+                unconstrained_path_length: Some(unconstrained.path_length() as u32),
+                excluded_on_shortest_paths,
+            });
+        }
+        match &hub_comparison {
+            // no shortest path goes through a hub, so leaving them out changes nothing
+            Some(comparison) if comparison.excluded_on_shortest_paths.is_empty() => {}
+            Some(_) => {
+                let excluded: Vec<u32> = job.excluded.iter().chain(&job.hubs).copied().collect();
+                outcome = search(&excluded);
+            }
+            // without hubs there's no path either (or the budget ran out)
+            None => {
+                if matches!(outcome, SearchOutcome::NoPath) {
+                    hub_comparison = Some(HubComparison {
+                        excluded_on_shortest_paths: Vec::new(),
+                        unconstrained_path_length: None,
+                    });
+                }
+            }
+        }
+    }
+    let elapsed_s = start_bfs.elapsed().as_secs_f64();
+    let node_count = searcher.node_count;
+
+    let (status, limit_hit, dag) = match outcome {
+        SearchOutcome::Found(dag) => (SearchStatus::Found, None, Some(dag)),
+        SearchOutcome::NoPath => (SearchStatus::NoPath, None, None),
+        SearchOutcome::Exhausted(limit_hit) => {
+            (SearchStatus::BudgetExhausted, Some(limit_hit), None)
+        }
+    };
+
+    let (total_paths, path_length) = match &dag {
+        Some(dag) => (dag.num_paths(), dag.path_length() as u32),
+        None => (0, 0),
+    };
+    let (paths, layered) = match job.format {
+        // nothing to list when the search gave up
+        _ if status == SearchStatus::BudgetExhausted => (None, None),
+        PathFormat::Paths => {
+            let paths = match &dag {
+                // seeking uses the path counts, an offset past the end costs nothing to answer
+                Some(dag) if (job.offset as u64) < total_paths => {
+                    let mut paths = dag.paths(&dataset.redirects_passed);
+                    paths.seek(job.offset as u64);
+                    paths.take(job.limit).collect()
+                }
+                _ => Vec::new(),
+            };
+            (Some(paths), None)
+        }
+        PathFormat::Dag => (
+            None,
+            dag.as_ref()
+                .map(|dag| dag.layered(&dataset.redirects_passed)),
+        ),
+    };
+
+    SearchOutput {
+        elapsed_s,
+        node_count,
+        status,
+        limit: limit_hit,
+        total_paths,
+        path_length,
+        paths,
+        layered,
+        hub_comparison,
+    }
+}
+
+/// k shortest loopless paths from start to goal, `job` has no waypoints or hubs
+fn run_k_shortest(
+    dataset: &Dataset,
+    job: &SearchJob,
+    k: usize,
+    limits: &SearchLimits,
+) -> SearchOutput {
+    let start_bfs = Instant::now();
+    let mut ctx = dataset.search_contexts.get();
+    let mut searcher = Searcher::new(&dataset.csr_graph, &mut ctx, limits);
+    let outcome = k_shortest::k_shortest_paths(
+        &mut searcher,
+        job.stops[0],
+        job.stops[job.stops.len() - 1],
+        k,
+        &job.excluded,
+    );
+    let elapsed_s = start_bfs.elapsed().as_secs_f64();
+    let node_count = searcher.node_count;
+
+    let (status, limit_hit, mut paths) = match outcome {
+        SearchOutcome::Found(k_paths) => (SearchStatus::Found, k_paths.stopped_by, k_paths.paths),
+        SearchOutcome::NoPath => (SearchStatus::NoPath, None, Vec::new()),
+        SearchOutcome::Exhausted(limit_hit) => {
+            (SearchStatus::BudgetExhausted, Some(limit_hit), Vec::new())
+        }
+    };
+    for path in &mut paths {
+        search::resolve_redirects(path, &dataset.redirects_passed);
+    }
+
+    SearchOutput {
+        elapsed_s,
+        node_count,
+        status,
+        limit: limit_hit,
+        total_paths: paths.len() as u64,
+        // the shortest one
+        path_length: paths.first().map_or(0, |path| path.len() as u32),
+        paths: (status != SearchStatus::BudgetExhausted)
+            .then(|| paths.into_iter().skip(job.offset).take(job.limit).collect()),
+        layered: None,
+        hub_comparison: None,
+    }
+}
+
+/// 503 telling the client to retry, for when the search queue is full
+pub fn queue_full_response() -> Response {
+    let body = json!({"error": "Too many searches right now, try again shortly"});
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Content-Type", "application/json")
+        .header("Retry-After", RETRY_AFTER_S.to_string())
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+/// 404 for a `wiki` this server doesn't have, listing the ones it does
+pub fn unknown_wiki_response(state: &AppState, wiki: Option<&str>) -> Response {
+    let wikis: Vec<&str> = state.datasets.keys().map(String::as_str).collect();
+    json_response(
+        json!({"error": format!(
+            "Unknown wiki '{}', this server has {}",
+            wiki.unwrap_or_default(),
+            wikis.join(", ")
+        )}),
+        StatusCode::NOT_FOUND,
+    )
+}
+
+/// Dense id of a page given by title or id, following redirects like start and end
+pub fn resolve_page(dataset: &Dataset, page: &PageRef) -> Option<u32> {
+    let dense_id = match page {
+        PageRef::Title(title) => dataset.title_to_dense_id.get(title)?,
+        PageRef::Id(orig_id) => dataset.orig_to_dense_id.get(*orig_id)?,
+    };
+    Some(match dataset.redirect_targets_dense.get(dense_id) {
+        u32::MAX => dense_id,
+        redirect => redirect,
+    })
+}
+
+/// Errors with the message for the first page that doesn't exist
+fn resolve_pages(dataset: &Dataset, pages: &[PageRef], what: &str) -> Result<Vec<u32>, String> {
+    pages
+        .iter()
+        .map(|page| {
+            resolve_page(dataset, page).ok_or_else(|| match page {
+                PageRef::Title(title) => format!("{} title '{}' not found", what, title),
+                PageRef::Id(orig_id) => format!("{} original ID '{}' not found", what, orig_id),
+            })
+        })
+        .collect()
+}
+
+/// Dense id of a search's start or end, given as a title or an original id, with redirects
+/// followed. `what` is "Start" or "End" for the error message.
+fn resolve_endpoint(
+    dataset: &Dataset,
+    title: &Option<String>,
+    orig_id: Option<u32>,
+    what: &str,
+) -> Result<u32, (StatusCode, String)> {
+    let dense_id = match (title, orig_id) {
+        (Some(title), None) => dataset.title_to_dense_id.get(title).ok_or_else(|| {
+            tracing::error!("bad request, reason={} title not found: {}", what, title);
+            (
+                StatusCode::NOT_FOUND,
+                format!(
+                    "{} title '{}' not found. This article might be too new, or check capitalization",
+                    what, title
+                ),
+            )
+        })?,
+        (None, Some(orig_id)) => dataset.orig_to_dense_id.get(orig_id).ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("{} original ID '{}' not found", what, orig_id),
+            )
+        })?,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Exactly one of {} or {}_id must be provided",
+                    what.to_lowercase(),
+                    what.to_lowercase()
+                ),
+            ))
+        }
+    };
+    Ok(match dataset.redirect_targets_dense.get(dense_id) {
+        u32::MAX => dense_id,
+        redirect => redirect,
+    })
+}
+
+/// True when the scc condensation shows some leg between consecutive stops has no path.
+/// Avoiding pages only removes routes, so this holds for every kind of search.
+fn proven_unreachable(dataset: &Dataset, stops: &[u32]) -> bool {
+    dataset.scc.as_ref().is_some_and(|scc| {
+        stops
+            .windows(2)
+            .any(|pair| scc.proves_unreachable(pair[0], pair[1]))
+    })
+}
+
+/// Record a finished search in the searches table, returns the id the leaderboards point to
+async fn log_search(
+    state: &AppState,
+    dataset: &Dataset,
+    user_id: &str,
+    start_id: u32,
+    goal_id: u32,
+    output: &SearchOutput,
+) -> String {
+    let search_id = Uuid::new_v4().to_string();
+    let start_id_orig = dataset.dense_id_to_orig.get(start_id);
+    let goal_id_orig = dataset.dense_id_to_orig.get(goal_id);
+    let created_at = Utc::now().format("%Y-%m-%dT%H:%M%z").to_string();
+    // leaderboard scores are u32
+    let num_paths = u32::try_from(output.total_paths).unwrap_or(u32::MAX);
+
+    match sqlx::query!(
+        r#"
+        INSERT INTO searches
+        (id, user_id, wiki, start_id, end_id, elapsed_s, nodes_visited, path_length, num_paths, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+        "#,
+        search_id,
+        user_id,
+        dataset.wiki,
+        start_id_orig,
+        goal_id_orig,
+        output.elapsed_s,
+        output.node_count,
+        output.path_length,
+        num_paths,
+        created_at
+    )
+    .execute(&state.sqlite_pool)
+    .await
+    {
+        Ok(r) => {
+            tracing::debug!("inserted search rows_affected={}", r.rows_affected());
+        }
+        Err(e) => {
+            tracing::error!("insert search failed: {:?}", e);
+        }
+    };
+    search_id
+}
+
 #[axum::debug_handler]
 pub async fn search_handler(
     State(state): State<Arc<AppState>>,
@@ -29,75 +417,139 @@ pub async fn search_handler(
         .map(|Extension(s)| s)
         .unwrap_or_else(|| "NO_USER".to_string());
 
-    // --- Resolve start ---
-    let start_id = match (&req.start, &req.start_id) {
-        (Some(title), None) => match state.title_to_dense_id.get(title) {
-            Some(id) => id,
-            None => {
-                tracing::error!("bad request, reason=Start title not found: {}", title);
-                return json_response(
-                    json!({"error": format!("Start title '{}' not found. This article might be too new, or check capitalization", title)}),
-                    StatusCode::NOT_FOUND,
-                );
-            }
-        },
-        (None, Some(orig_id)) => match state.orig_to_dense_id.get(*orig_id) {
-            Some(id) => id,
-            None => {
-                return json_response(
-                    json!({"error": format!("Start original ID '{}' not found", orig_id)}),
-                    StatusCode::NOT_FOUND,
-                )
-            }
-        },
-        _ => {
-            return json_response(
-                json!({"error": "Exactly one of start or start_id must be provided"}),
-                StatusCode::BAD_REQUEST,
-            )
+    let Some(dataset) = state.dataset(req.wiki.as_deref()).cloned() else {
+        return unknown_wiki_response(&state, req.wiki.as_deref());
+    };
+
+    // --- Resolve start and end, following redirects ---
+    let (start_id, goal_id) = match (
+        resolve_endpoint(&dataset, &req.start, req.start_id, "Start"),
+        resolve_endpoint(&dataset, &req.end, req.end_id, "End"),
+    ) {
+        (Ok(start_id), Ok(goal_id)) => (start_id, goal_id),
+        (Err((status, error)), _) | (_, Err((status, error))) => {
+            return json_response(json!({ "error": error }), status)
         }
     };
 
-    // --- Resolve end ---
-    let goal_id = match (&req.end, &req.end_id) {
-        (Some(title), None) => match state.title_to_dense_id.get(title) {
-            Some(id) => id,
-            None => {
-                tracing::error!("bad request, reason=end title not found: {}", title);
+    // --- Resolve constraints ---
+    if req.avoid.len() > MAX_AVOID || req.via.len() > MAX_VIA {
+        return json_response(
+            json!({"error": format!("At most {} avoided pages and {} waypoints", MAX_AVOID, MAX_VIA)}),
+            StatusCode::BAD_REQUEST,
+        );
+    }
+    let (excluded, via) = match (
+        resolve_pages(&dataset, &req.avoid, "Avoided page"),
+        resolve_pages(&dataset, &req.via, "Waypoint"),
+    ) {
+        (Ok(excluded), Ok(via)) => (excluded, via),
+        (Err(error), _) | (_, Err(error)) => {
+            return json_response(json!({ "error": error }), StatusCode::NOT_FOUND)
+        }
+    };
+    let mut stops = Vec::with_capacity(via.len() + 2);
+    stops.push(start_id);
+    stops.extend_from_slice(&via);
+    stops.push(goal_id);
+    if stops.iter().any(|stop| excluded.contains(stop)) {
+        return json_response(
+            json!({"error": "Start, end and waypoints can't be avoided"}),
+            StatusCode::BAD_REQUEST,
+        );
+    }
 
+    if let Some(k) = req.k {
+        if k == 0 || k > MAX_K {
+            return json_response(
+                json!({"error": format!("k must be between 1 and {}", MAX_K)}),
+                StatusCode::BAD_REQUEST,
+            );
+        }
+        if !via.is_empty() || req.hubs.is_some() || req.format == PathFormat::Dag {
+            return json_response(
+                json!({"error": "k can't be combined with via, hubs or the dag format"}),
+                StatusCode::BAD_REQUEST,
+            );
+        }
+    }
+
+    // --- Pick hubs ---
+    let num_nodes = dataset.csr_graph.num_nodes();
+    let num_hubs = match req.hubs {
+        None => Some(0),
+        Some(HubFilter::MaxDegree(max_degree)) => dataset
+            .hub_degrees
+            .count_above_degree(max_degree, num_nodes),
+        Some(HubFilter::Percentile(percentile)) => {
+            if !(0.0..=100.0).contains(&percentile) {
                 return json_response(
-                    json!({"error": format!("End title '{}' not found. Check capitalization", title)}),
-                    StatusCode::NOT_FOUND,
+                    json!({"error": "Hub percentile must be between 0 and 100"}),
+                    StatusCode::BAD_REQUEST,
                 );
             }
-        },
-        (None, Some(orig_id)) => match state.orig_to_dense_id.get(*orig_id) {
-            Some(id) => id,
-            None => {
-                return json_response(
-                    json!({"error": format!("End original ID '{}' not found", orig_id)}),
-                    StatusCode::NOT_FOUND,
-                )
-            }
-        },
-        _ => {
-            return json_response(
-                json!({"error": "Exactly one of end or end_id must be provided"}),
-                StatusCode::BAD_REQUEST,
-            )
+            dataset
+                .hub_degrees
+                .count_above_percentile(percentile, num_nodes)
         }
     };
-
-    // --- Resolve redirects ---
-    let start_id = match state.redirect_targets_dense.get(start_id) {
-        u32::MAX => start_id,
-        redirect => redirect,
+    // the table only has the biggest hubs, leaving out some of the ones asked for would be a lie
+    let Some(num_hubs) = num_hubs else {
+        let num_in_table = dataset.hub_degrees.num_hubs();
+        return json_response(
+            json!({"error": format!(
+                "That leaves out more than the {} biggest hubs, use a max_degree of at least {} or a higher percentile",
+                num_in_table,
+                dataset.hub_degrees.degree(num_in_table - 1)
+            )}),
+            StatusCode::BAD_REQUEST,
+        );
     };
-    let goal_id = match state.redirect_targets_dense.get(goal_id) {
-        u32::MAX => goal_id,
-        redirect => redirect,
+    // start, end and waypoints stay in even if they're hubs
+    let hubs: Vec<u32> = dataset
+        .hub_degrees
+        .top(num_hubs)
+        .into_iter()
+        .filter(|hub| !stops.contains(hub))
+        .collect();
+    let degree_threshold = match num_hubs {
+        0 => 0,
+        n => dataset.hub_degrees.degree(n - 1),
     };
 
+    let constrained =
+        !excluded.is_empty() || !via.is_empty() || req.hubs.is_some() || req.k.is_some();
+
+    // --- Unreachable pairs don't need a search ---
+    if proven_unreachable(&dataset, &stops) {
+        tracing::debug!("unreachable by scc: {} -> {}", start_id, goal_id);
+        log_search(
+            &state,
+            &dataset,
+            &user_id,
+            start_id,
+            goal_id,
+            &SearchOutput::unsearched(),
+        )
+        .await;
+        let response = PathResponse {
+            status: SearchStatus::NoPath,
+            limit: None,
+            elapsed_s: start_req.elapsed().as_secs_f64(),
+            total_paths: 0,
+            paths: (req.format == PathFormat::Paths).then(Vec::new),
+            dag: None,
+            hubs: None,
+            leaderboard_longest_rank: None,
+            leaderboard_most_rank: None,
+        };
+        return Response::builder()
+            .header("Content-Type", "application/json")
+            .header("Cache-Control", "public, max-age=31536000, immutable") // ~1 year
+            .body(Body::from(serde_json::to_vec(&response).unwrap()))
+            .unwrap();
+    }
+
     // let mut hasher = FxHasher::default();
     // start_id.hash(&mut hasher);
     // goal_id.hash(&mut hasher);
@@ -118,107 +570,201 @@ pub async fn search_handler(
     //     }
     // }
 
-    // --- Run BFS ---
-    let start_bfs = Instant::now();
-    let mut node_count = 0;
-    let result = search::bi_bfs_csr(
-        &*state.csr_graph,
-        start_id,
-        goal_id,
-        50,
-        &state.redirects_passed,
-        &mut node_count,
-    )
-    .unwrap_or_default();
-    let elapsed_s = start_bfs.elapsed().as_secs_f64();
-
-    // --- Convert paths ---
-    let paths: Vec<Vec<PathNode>> = result
-        .into_iter()
-        .map(|path| {
-            path.into_iter()
-                .map(|dense_id| {
-                    if req.output_as_ids {
-                        PathNode::Id(state.dense_id_to_orig.get(dense_id))
-                    } else {
-                        PathNode::Title(state.dense_id_to_title.get(dense_id).to_string())
-                    }
-                })
-                .collect()
-        })
-        .collect();
+    // --- Run BFS on the search executor ---
+    let limit = req.limit.unwrap_or(DEFAULT_PATH_LIMIT).min(MAX_PATH_LIMIT);
+    let num_excluded = hubs.len();
+    let job = SearchJob {
+        stops,
+        excluded,
+        hubs,
+        k: req.k,
+        format: req.format,
+        offset: req.offset,
+        limit,
+    };
+    let cancel = CancelOnDrop::new();
+    let limits = SearchLimits {
+        max_depth: MAX_SEARCH_DEPTH,
+        max_nodes: state.env.search_max_nodes,
+        deadline: Some(Instant::now() + Duration::from_millis(state.env.search_timeout_ms)),
+        cancel: Some(cancel.0.clone()),
+    };
+    let search_dataset = dataset.clone();
+    let submitted = state
+        .search_executor
+        .try_submit(move || run_search(&search_dataset, &job, &limits));
+    let receiver = match submitted {
+        Ok(receiver) => receiver,
+        Err(QueueFull) => {
+            tracing::warn!(
+                "search rejected, queue full: queue_depth={} in_flight={}",
+                state.search_executor.queue_depth(),
+                state.search_executor.in_flight()
+            );
+            return queue_full_response();
+        }
+    };
+    tracing::info!(
+        "search submitted: queue_depth={} in_flight={}",
+        state.search_executor.queue_depth(),
+        state.search_executor.in_flight()
+    );
 
-    let sql_time = Instant::now();
-    let num_paths = paths.len() as u32;
-    let path_length = if let Some(first_path) = paths.first() {
-        first_path.len() as u32
-    } else {
-        0
+    let output = match receiver.await {
+        Ok(output) => output,
+        Err(_) => {
+            tracing::error!("search job failed: {} -> {}", start_id, goal_id);
+            return json_response(
+                json!({"error": "Search failed"}),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
     };
+    drop(cancel);
 
-    if num_paths == 0 {
-        println!("no path found");
+    // a search that gave up says nothing about the pair, so it isn't recorded or cached
+    if output.status == SearchStatus::BudgetExhausted {
+        tracing::info!(
+            "search budget exhausted ({:?}) after {} nodes: {} -> {}",
+            output.limit,
+            output.node_count,
+            start_id,
+            goal_id
+        );
+        let response = PathResponse {
+            status: output.status,
+            limit: output.limit,
+            elapsed_s: output.elapsed_s,
+            total_paths: output.total_paths,
+            paths: None,
+            dag: None,
+            hubs: None,
+            leaderboard_longest_rank: None,
+            leaderboard_most_rank: None,
+        };
+        return Response::builder()
+            .header("Content-Type", "application/json")
+            .header("Cache-Control", "no-store")
+            .body(Body::from(serde_json::to_vec(&response).unwrap()))
+            .unwrap();
     }
-    let search_id = Uuid::new_v4().to_string();
-    let start_id_orig = state.dense_id_to_orig.get(start_id);
-    let goal_id_orig = state.dense_id_to_orig.get(goal_id);
-    let created_at = Utc::now().format("%Y-%m-%dT%H:%M%z").to_string();
 
-    match sqlx::query!(
-        r#"
-        INSERT INTO searches
-        (id, user_id, start_id, end_id, elapsed_s, nodes_visited, path_length, num_paths, created_at)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
-        "#,
-        search_id,
-        user_id,
-        start_id_orig,
-        goal_id_orig,
+    let sql_time = Instant::now();
+    let search_id = log_search(&state, &dataset, &user_id, start_id, goal_id, &output).await;
+    let SearchOutput {
         elapsed_s,
-        node_count,
+        status,
+        limit: limit_hit,
+        total_paths,
         path_length,
-        num_paths,
-        created_at
-    )
-    .execute(&state.sqlite_pool)
-    .await{
-        Ok(r) => {
-            tracing::debug!("inserted search rows_affected={}", r.rows_affected());
-        }
-        Err(e) => {
-            tracing::error!("insert search failed: {:?}", e);
+        paths: raw_paths,
+        layered,
+        hub_comparison,
+        ..
+    } = output;
+
+    let to_node = |dense_id: u32| {
+        if req.output_as_ids {
+            PathNode::Id(dataset.dense_id_to_orig.get(dense_id))
+        } else {
+            PathNode::Title(dataset.dense_id_to_title.get(dense_id).to_string())
         }
     };
 
-    let leaderboard_longest_rank = try_add_to_leaderboard(
-        &state.sqlite_pool,
-        &state.redis_pool,
-        "longest",
-        start_id_orig,
-        goal_id_orig,
-        path_length,
-        &search_id,
-        &user_id,
-        state.env.leaderboard_limit,
-    )
-    .await;
-    let leaderboard_most_rank = try_add_to_leaderboard(
-        &state.sqlite_pool,
-        &state.redis_pool,
-        "most",
-        start_id_orig,
-        goal_id_orig,
-        num_paths,
-        &search_id,
-        &user_id,
-        state.env.leaderboard_limit,
-    )
-    .await;
+    // --- Convert paths ---
+    let paths: Option<Vec<Vec<PathNode>>> = raw_paths.map(|raw_paths| {
+        raw_paths
+            .into_iter()
+            .map(|path| path.into_iter().map(to_node).collect())
+            .collect()
+    });
+    let dag_response = layered.map(|layered| DagResponse {
+        layers: layered
+            .layers
+            .into_iter()
+            .map(|layer| layer.into_iter().map(to_node).collect())
+            .collect(),
+        edges: layered
+            .edges
+            .into_iter()
+            .map(|layer_edges| {
+                layer_edges
+                    .into_iter()
+                    .map(|edge| DagEdgeResponse {
+                        from: edge.from,
+                        to: edge.to,
+                        redirect: edge.redirect.map(to_node),
+                    })
+                    .collect()
+            })
+            .collect(),
+    });
+
+    let hub_report = hub_comparison.map(|comparison| HubReport {
+        num_excluded,
+        degree_threshold,
+        excluded_on_shortest_paths: comparison
+            .excluded_on_shortest_paths
+            .into_iter()
+            .map(to_node)
+            .collect(),
+        unconstrained_path_length: comparison.unconstrained_path_length,
+        extra_length: comparison
+            .unconstrained_path_length
+            .filter(|_| status == SearchStatus::Found)
+            .map(|unconstrained| path_length - unconstrained),
+    });
+
+    // leaderboard scores are u32
+    let num_paths = u32::try_from(total_paths).unwrap_or(u32::MAX);
+
+    if num_paths == 0 {
+        println!("no path found");
+    }
+    let start_id_orig = dataset.dense_id_to_orig.get(start_id);
+    let goal_id_orig = dataset.dense_id_to_orig.get(goal_id);
+
+    // the leaderboards are for a pair's unconstrained shortest paths
+    let (leaderboard_longest_rank, leaderboard_most_rank) = if constrained {
+        (None, None)
+    } else {
+        let leaderboard_search = LeaderboardSearch {
+            wiki: &dataset.wiki,
+            start_id: start_id_orig,
+            end_id: goal_id_orig,
+            search_id: &search_id,
+            user_id: &user_id,
+        };
+        let longest_rank = try_add_to_leaderboard(
+            &state.sqlite_pool,
+            &state.redis_pool,
+            &leaderboard_search,
+            "longest",
+            path_length,
+            state.env.leaderboard_limit,
+        )
+        .await;
+        let most_rank = try_add_to_leaderboard(
+            &state.sqlite_pool,
+            &state.redis_pool,
+            &leaderboard_search,
+            "most",
+            num_paths,
+            state.env.leaderboard_limit,
+        )
+        .await;
+        (longest_rank, most_rank)
+    };
 
     tracing::debug!("db stuff took {:?}", sql_time.elapsed());
     let response = PathResponse {
+        status,
+        limit: limit_hit,
         elapsed_s,
+        total_paths,
         paths,
+        dag: dag_response,
+        hubs: hub_report,
         leaderboard_longest_rank,
         leaderboard_most_rank,
     };
@@ -240,3 +786,188 @@ pub async fn search_handler(
 
     // Json(json!(response))
 }
+
+/// Searches a list of start/end pairs in one request, all queued on the search executor at
+/// once. Every pair gets a result or an error, in the order they were given. Batch searches
+/// are logged but don't go on the leaderboards.
+pub async fn search_batch_handler(
+    State(state): State<Arc<AppState>>,
+    maybe_user: Option<Extension<String>>,
+    Json(req): Json<BatchSearchRequest>,
+) -> impl IntoResponse {
+    let start_req = Instant::now();
+
+    let user_id: String = maybe_user
+        .map(|Extension(s)| s)
+        .unwrap_or_else(|| "NO_USER".to_string());
+
+    if req.pairs.is_empty() || req.pairs.len() > MAX_BATCH_PAIRS {
+        return json_response(
+            json!({"error": format!("A batch needs between 1 and {} pairs", MAX_BATCH_PAIRS)}),
+            StatusCode::BAD_REQUEST,
+        );
+    }
+    let Some(dataset) = state.dataset(req.wiki.as_deref()).cloned() else {
+        return unknown_wiki_response(&state, req.wiki.as_deref());
+    };
+    let limit = req.limit.unwrap_or(DEFAULT_PATH_LIMIT).min(MAX_PATH_LIMIT);
+
+    // --- Resolve and run every pair, a few at a time ---
+    // one deadline and cancel flag for the whole batch
+    let cancel = CancelOnDrop::new();
+    let limits = SearchLimits {
+        max_depth: MAX_SEARCH_DEPTH,
+        max_nodes: state.env.search_max_nodes,
+        deadline: Some(Instant::now() + Duration::from_millis(state.env.search_timeout_ms)),
+        cancel: Some(cancel.0.clone()),
+    };
+    // filled in as they finish, in_flight holds (pair index, start, goal, receiver), oldest first
+    let mut results: Vec<Option<BatchSearchResult>> = req.pairs.iter().map(|_| None).collect();
+    let mut in_flight = VecDeque::with_capacity(MAX_BATCH_IN_FLIGHT);
+    for (i, pair) in req.pairs.iter().enumerate() {
+        let start = resolve_endpoint(&dataset, &pair.start, pair.start_id, "Start");
+        let goal = resolve_endpoint(&dataset, &pair.end, pair.end_id, "End");
+        let (start_id, goal_id) = match (start, goal) {
+            (Ok(start_id), Ok(goal_id)) => (start_id, goal_id),
+            // not logged, same as a 404 from /search
+            (start, goal) => {
+                let (_, error) = start.and(goal).unwrap_err();
+                results[i] = Some(BatchSearchResult::Failed { error });
+                continue;
+            }
+        };
+        if proven_unreachable(&dataset, &[start_id, goal_id]) {
+            let output = SearchOutput::unsearched();
+            log_search(&state, &dataset, &user_id, start_id, goal_id, &output).await;
+            results[i] = Some(batch_result(&dataset, output, req.output_as_ids));
+            continue;
+        }
+
+        // wait for the oldest one first, a batch never takes more than its share of the queue
+        if in_flight.len() == MAX_BATCH_IN_FLIGHT {
+            let (j, start_id, goal_id, receiver) = in_flight.pop_front().unwrap();
+            results[j] = Some(
+                finish_batch_search(
+                    &state,
+                    &dataset,
+                    &user_id,
+                    req.output_as_ids,
+                    start_id,
+                    goal_id,
+                    receiver,
+                )
+                .await,
+            );
+        }
+
+        let job = SearchJob {
+            stops: vec![start_id, goal_id],
+            excluded: Vec::new(),
+            hubs: Vec::new(),
+            k: None,
+            format: PathFormat::Paths,
+            offset: 0,
+            limit,
+        };
+        let search_dataset = dataset.clone();
+        let limits = limits.clone();
+        match state
+            .search_executor
+            .try_submit(move || run_search(&search_dataset, &job, &limits))
+        {
+            Ok(receiver) => in_flight.push_back((i, start_id, goal_id, receiver)),
+            // not searched so not logged, like a 503 from /search
+            Err(QueueFull) => {
+                tracing::warn!(
+                    "batch search rejected, queue full: queue_depth={} in_flight={}",
+                    state.search_executor.queue_depth(),
+                    state.search_executor.in_flight()
+                );
+                results[i] = Some(BatchSearchResult::Failed {
+                    error: "Too many searches right now, try again shortly".to_string(),
+                });
+            }
+        }
+    }
+    for (i, start_id, goal_id, receiver) in in_flight {
+        results[i] = Some(
+            finish_batch_search(
+                &state,
+                &dataset,
+                &user_id,
+                req.output_as_ids,
+                start_id,
+                goal_id,
+                receiver,
+            )
+            .await,
+        );
+    }
+    tracing::info!(
+        "batch of {} searches done: queue_depth={} in_flight={}",
+        req.pairs.len(),
+        state.search_executor.queue_depth(),
+        state.search_executor.in_flight()
+    );
+    let results = results.into_iter().map(Option::unwrap).collect();
+    drop(cancel);
+
+    let response = BatchSearchResponse {
+        elapsed_s: start_req.elapsed().as_secs_f64(),
+        results,
+    };
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .header("Cache-Control", "no-store")
+        .body(Body::from(serde_json::to_vec(&response).unwrap()))
+        .unwrap()
+}
+
+/// Wait for one search of a batch and log it
+async fn finish_batch_search(
+    state: &AppState,
+    dataset: &Dataset,
+    user_id: &str,
+    output_as_ids: bool,
+    start_id: u32,
+    goal_id: u32,
+    receiver: oneshot::Receiver<SearchOutput>,
+) -> BatchSearchResult {
+    let Ok(output) = receiver.await else {
+        tracing::error!("search job failed: {} -> {}", start_id, goal_id);
+        return BatchSearchResult::Failed {
+            error: "Search failed".to_string(),
+        };
+    };
+    // same as a single search, one that gave up isn't recorded
+    if output.status != SearchStatus::BudgetExhausted {
+        log_search(state, dataset, user_id, start_id, goal_id, &output).await;
+    }
+    batch_result(dataset, output, output_as_ids)
+}
+
+fn batch_result(dataset: &Dataset, output: SearchOutput, output_as_ids: bool) -> BatchSearchResult {
+    let to_node = |dense_id: u32| {
+        if output_as_ids {
+            PathNode::Id(dataset.dense_id_to_orig.get(dense_id))
+        } else {
+            PathNode::Title(dataset.dense_id_to_title.get(dense_id).to_string())
+        }
+    };
+    BatchSearchResult::Done(PathResponse {
+        status: output.status,
+        limit: output.limit,
+        elapsed_s: output.elapsed_s,
+        total_paths: output.total_paths,
+        paths: output.paths.map(|paths| {
+            paths
+                .into_iter()
+                .map(|path| path.into_iter().map(to_node).collect())
+                .collect()
+        }),
+        dag: None,
+        hubs: None,
+        leaderboard_longest_rank: None,
+        leaderboard_most_rank: None,
+    })
+}
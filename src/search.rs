@@ -1,13 +1,127 @@
+use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Serialize;
 use std::{
-    collections::VecDeque,
+    collections::{hash_map::Entry, VecDeque},
     fs::File,
     io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
     time::Instant,
 };
 
 use crate::graph::*;
 use crate::mmap_structs::*;
+use crate::paths::DataDir;
+use crate::search_context::{SearchContext, SearchContextPool, StampedDepths};
+
+// frontiers at least this big get expanded on every core, smaller ones aren't worth the threads
+pub const PARALLEL_FRONTIER_THRESHOLD: usize = 100_000;
+// how many expanded nodes between looking at the clock and the cancel flag
+const CLOCK_CHECK_INTERVAL: u32 = 1024;
+
+/// Which limit stopped a search
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitHit {
+    MaxDepth,
+    MaxNodes,
+    Deadline,
+    Cancelled,
+}
+
+/// Budget for one search, checked as nodes get expanded so a search stops soon after hitting a limit.
+#[derive(Clone)]
+pub struct SearchLimits {
+    // combined depth of both sides for the bidirectional searches
+    pub max_depth: u8,
+    // nodes whose edges get scanned
+    pub max_nodes: Option<u32>,
+    pub deadline: Option<Instant>,
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+/// What the searches of one request share: the graph, a context to search with,
+/// the budget and how many nodes have been expanded against it so far.
+pub struct Searcher<'a, G> {
+    pub graph: &'a G,
+    pub ctx: &'a mut SearchContext,
+    pub limits: &'a SearchLimits,
+    pub node_count: u32,
+}
+
+impl<'a, G> Searcher<'a, G> {
+    pub fn new(graph: &'a G, ctx: &'a mut SearchContext, limits: &'a SearchLimits) -> Self {
+        Searcher {
+            graph,
+            ctx,
+            limits,
+            node_count: 0,
+        }
+    }
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        SearchLimits::with_max_depth(u8::MAX)
+    }
+}
+
+impl SearchLimits {
+    pub fn with_max_depth(max_depth: u8) -> Self {
+        SearchLimits {
+            max_depth,
+            max_nodes: None,
+            deadline: None,
+            cancel: None,
+        }
+    }
+
+    /// Call once per expanded node with the running count
+    #[inline]
+    pub fn check(&self, expanded: u32) -> Result<(), LimitHit> {
+        if self.max_nodes.is_some_and(|max_nodes| expanded > max_nodes) {
+            return Err(LimitHit::MaxNodes);
+        }
+        if !expanded.is_multiple_of(CLOCK_CHECK_INTERVAL) {
+            return Ok(());
+        }
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+        {
+            return Err(LimitHit::Cancelled);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(LimitHit::Deadline);
+        }
+        Ok(())
+    }
+}
+
+/// Result of a search with limits, "there is no path" and "gave up" are different answers
+#[derive(Debug)]
+pub enum SearchOutcome<T> {
+    Found(T),
+    // everything reachable was explored
+    NoPath,
+    Exhausted(LimitHit),
+}
+
+impl<T> SearchOutcome<T> {
+    pub fn found(self) -> Option<T> {
+        match self {
+            SearchOutcome::Found(found) => Some(found),
+            _ => None,
+        }
+    }
+}
 
 fn process_neighbor(
     neighbor: u32,
@@ -158,14 +272,18 @@ pub fn bfs_adj_list(
     if goal_found_at_depth.is_some() {
         let elapsed = now.elapsed();
         println!("Elapsed: {:.2?}", elapsed);
-        return Some(reconstruct_all_paths(
-            start,
-            goal,
-            &parents,
-            redirects_passed,
-            true,
-            backwards,
-        ));
+        // a bidirectional search that met at the goal, with nothing on the backward side
+        let no_parents = FxHashMap::default();
+        let paths = PathIter::new(start, &[goal], goal, &parents, &no_parents, None)
+            .map(|mut path| {
+                if backwards {
+                    path.reverse();
+                }
+                resolve_redirects(&mut path, redirects_passed);
+                path
+            })
+            .collect();
+        return Some(paths);
     }
 
     None
@@ -278,136 +396,576 @@ pub fn bi_bfs_adj_list(
         let elapsed = now.elapsed();
         println!("Elapsed: {:.2?}", elapsed);
 
-        return Some(merge_all_paths(
-            start,
-            &meet_nodes,
-            goal,
-            &parents_fwd,
-            &parents_bwd,
-            redirects_passed,
-            true,
-        ));
+        return Some(
+            PathIter::new(
+                start,
+                &meet_nodes,
+                goal,
+                &parents_fwd,
+                &parents_bwd,
+                Some(redirects_passed),
+            )
+            .collect(),
+        );
     }
 
     None
 }
 
-pub fn reconstruct_all_paths(
-    start: u32,
-    goal: u32,
-    parents: &FxHashMap<u32, Vec<u32>>,
-    redirects_passed: &RedirectsPassedMmap,
-    // redirects_passed: &FxHashMap<(u32, u32), u32>,
-    return_redirects: bool,
-    reverse: bool,
-) -> Vec<Vec<u32>> {
-    let mut all_paths = Vec::new();
-
-    // Stack holds (current_node, current_path)
-    // current_path is goal->...->current_node order
-    let mut stack: Vec<(u32, Vec<u32>)> = Vec::new();
-    stack.push((goal, vec![goal]));
-
-    while let Some((node, path)) = stack.pop() {
-        if node == start {
-            let mut complete_path = path.clone();
-            complete_path.reverse(); // make it start->...->goal
-            all_paths.push(complete_path);
-        } else if let Some(pars) = parents.get(&node) {
-            for &p in pars {
-                let mut new_path = path.clone();
-                new_path.push(p);
-                stack.push((p, new_path));
+/// Replace each node with the redirect that was followed to reach it from the previous node.
+/// Walks backwards so every lookup still sees the unresolved previous node.
+pub fn resolve_redirects(path: &mut [u32], redirects_passed: &RedirectsPassedMmap) {
+    for i in (1..path.len()).rev() {
+        if let Some(redirect) = redirects_passed.get(path[i - 1], path[i]) {
+            path[i] = redirect;
+        }
+    }
+}
+
+/// Walks every chain from `from` up to `root` in a parent DAG, one chain at a time,
+/// like an odometer over the parent choices. Nothing but the current chain is kept in memory.
+struct ParentChains<'a> {
+    parents: &'a FxHashMap<u32, Vec<u32>>,
+    root: u32,
+    // (node, index of the parent chosen for it), from -> ... -> root
+    // empty once every chain has been visited
+    stack: Vec<(u32, usize)>,
+}
+
+impl<'a> ParentChains<'a> {
+    fn new(parents: &'a FxHashMap<u32, Vec<u32>>, from: u32, root: u32) -> Self {
+        let mut chains = ParentChains {
+            parents,
+            root,
+            stack: Vec::new(),
+        };
+        chains.descend(from);
+        chains
+    }
+
+    /// Push `node` and follow first parents until the root.
+    fn descend(&mut self, mut node: u32) {
+        loop {
+            self.stack.push((node, 0));
+            if node == self.root {
+                return;
+            }
+            match self.parents.get(&node) {
+                Some(pars) => node = pars[0],
+                None => {
+                    // dead end, can't happen for a bfs parent map
+                    self.stack.clear();
+                    return;
+                }
             }
         }
     }
 
-    // If reverse flag is set, reverse each found path back
-    if reverse {
-        for path in &mut all_paths {
-            path.reverse();
+    /// Chain number `index` in the order `advance` visits them, picking each parent by how many
+    /// chains go through it instead of walking past the earlier ones. Exhausted if there's no such chain.
+    fn at(
+        parents: &'a FxHashMap<u32, Vec<u32>>,
+        from: u32,
+        root: u32,
+        mut index: u64,
+        memo: &mut FxHashMap<u32, u64>,
+    ) -> Self {
+        let mut chains = ParentChains {
+            parents,
+            root,
+            stack: Vec::new(),
+        };
+        let mut node = from;
+        while node != root {
+            // the parent of the bottom of the stack changes slowest, so it's picked first
+            let choice = parents.get(&node).and_then(|pars| {
+                pars.iter().position(|&p| {
+                    let count = count_chains(p, root, parents, memo);
+                    if index < count {
+                        return true;
+                    }
+                    index -= count;
+                    false
+                })
+            });
+            let Some(idx) = choice else {
+                chains.stack.clear();
+                return chains;
+            };
+            chains.stack.push((node, idx));
+            node = parents[&node][idx];
         }
+        chains.stack.push((root, 0));
+        chains
     }
 
-    if !return_redirects {
-        return all_paths;
+    fn is_exhausted(&self) -> bool {
+        self.stack.is_empty()
     }
 
-    // Apply redirect resolution
-    let mut resolved_paths = Vec::new();
-    for path in all_paths {
-        if path.len() < 2 {
-            resolved_paths.push(path);
-            continue;
-        }
+    /// Current chain, from -> ... -> root
+    fn chain(&self) -> impl Iterator<Item = u32> + '_ {
+        self.stack.iter().map(|&(node, _)| node)
+    }
 
-        let mut resolved_path = Vec::with_capacity(path.len());
-        resolved_path.push(path[0]);
-        for window in path.windows(2) {
-            let prev_node = window[0];
-            let node = window[1];
-            resolved_path.push(redirects_passed.get(prev_node, node).unwrap_or(node));
+    /// Move to the next chain. Returns false (and becomes exhausted) after the last one.
+    fn advance(&mut self) -> bool {
+        while let Some((node, idx)) = self.stack.pop() {
+            if node == self.root {
+                continue;
+            }
+            let pars = &self.parents[&node];
+            if idx + 1 < pars.len() {
+                self.stack.push((node, idx + 1));
+                self.descend(pars[idx + 1]);
+                return !self.is_exhausted();
+            }
         }
-        resolved_paths.push(resolved_path);
+        false
     }
+}
 
-    // there shouldn't be any duplicates
-    resolved_paths
+/// Number of chains from `node` up to `root`, memoized per node.
+/// Recursion depth is bounded by the bfs depth.
+fn count_chains(
+    node: u32,
+    root: u32,
+    parents: &FxHashMap<u32, Vec<u32>>,
+    memo: &mut FxHashMap<u32, u64>,
+) -> u64 {
+    if node == root {
+        return 1;
+    }
+    if let Some(&count) = memo.get(&node) {
+        return count;
+    }
+    let count = parents.get(&node).map_or(0, |pars| {
+        pars.iter().fold(0u64, |acc, &p| {
+            acc.saturating_add(count_chains(p, root, parents, memo))
+        })
+    });
+    memo.insert(node, count);
+    count
 }
 
-pub fn merge_all_paths(
+/// Lazily yields every shortest path start -> ... -> goal of a bidirectional search,
+/// meet node by meet node, without building the cartesian product up front.
+pub struct PathIter<'a> {
     start: u32,
-    meet_nodes: &Vec<u32>,
     goal: u32,
-    parents_fwd: &FxHashMap<u32, Vec<u32>>,
-    parents_bwd: &FxHashMap<u32, Vec<u32>>,
-    redirects_passed: &RedirectsPassedMmap,
-    // redirects_passed: &FxHashMap<(u32, u32), u32>,
-    return_redirects: bool,
-) -> Vec<Vec<u32>> {
-    let mut final_paths: Vec<Vec<u32>> = Vec::new();
+    meet_nodes: &'a [u32],
+    parents_fwd: &'a FxHashMap<u32, Vec<u32>>,
+    parents_bwd: &'a FxHashMap<u32, Vec<u32>>,
+    redirects_passed: Option<&'a RedirectsPassedMmap>,
+    meet_idx: usize,
+    // chains for meet_nodes[meet_idx], fwd is the outer loop, bwd the inner one
+    chains: Option<(ParentChains<'a>, ParentChains<'a>)>,
+}
 
-    for &meet in meet_nodes {
-        let fwd_parts = reconstruct_all_paths(
+impl<'a> PathIter<'a> {
+    pub fn new(
+        start: u32,
+        meet_nodes: &'a [u32],
+        goal: u32,
+        parents_fwd: &'a FxHashMap<u32, Vec<u32>>,
+        parents_bwd: &'a FxHashMap<u32, Vec<u32>>,
+        redirects_passed: Option<&'a RedirectsPassedMmap>,
+    ) -> Self {
+        PathIter {
             start,
-            meet,
-            parents_fwd,
-            redirects_passed,
-            return_redirects,
-            false,
-        );
-        let bwd_parts = reconstruct_all_paths(
             goal,
-            meet,
+            meet_nodes,
+            parents_fwd,
             parents_bwd,
             redirects_passed,
-            return_redirects,
-            true,
-        );
+            meet_idx: 0,
+            chains: None,
+        }
+    }
+}
 
-        for fwd_part in &fwd_parts {
-            for bwd_part in &bwd_parts {
-                let mut combined = fwd_part.clone();
-                // skip the meet from backward part because if the meet is a redirect
-                // foward part's meet will correctly be a redirect while backward's meet will not (since it's the first element)
-                combined.extend_from_slice(&bwd_part[1..]);
-                final_paths.push(combined)
+impl PathIter<'_> {
+    /// Jump to path number `index`, skipping whole meet nodes and parent choices by their path
+    /// counts so the paths before it are never built. Empty if there are `index` paths or fewer.
+    pub fn seek(&mut self, mut index: u64) {
+        let mut memo_fwd: FxHashMap<u32, u64> = FxHashMap::default();
+        let mut memo_bwd: FxHashMap<u32, u64> = FxHashMap::default();
+        self.chains = None;
+        self.meet_idx = self.meet_nodes.len();
+        for (i, &meet) in self.meet_nodes.iter().enumerate() {
+            let fwd = count_chains(meet, self.start, self.parents_fwd, &mut memo_fwd);
+            let bwd = count_chains(meet, self.goal, self.parents_bwd, &mut memo_bwd);
+            let through_meet = fwd.saturating_mul(bwd);
+            if index < through_meet {
+                // fwd is the outer loop
+                self.meet_idx = i;
+                self.chains = Some((
+                    ParentChains::at(
+                        self.parents_fwd,
+                        meet,
+                        self.start,
+                        index / bwd,
+                        &mut memo_fwd,
+                    ),
+                    ParentChains::at(
+                        self.parents_bwd,
+                        meet,
+                        self.goal,
+                        index % bwd,
+                        &mut memo_bwd,
+                    ),
+                ));
+                return;
             }
+            index -= through_meet;
         }
     }
+}
+
+impl Iterator for PathIter<'_> {
+    type Item = Vec<u32>;
+
+    fn next(&mut self) -> Option<Vec<u32>> {
+        loop {
+            let (start, goal) = (self.start, self.goal);
+            let (parents_fwd, parents_bwd) = (self.parents_fwd, self.parents_bwd);
+            let meet = *self.meet_nodes.get(self.meet_idx)?;
+            let (fwd, bwd) = self.chains.get_or_insert_with(|| {
+                (
+                    ParentChains::new(parents_fwd, meet, start),
+                    ParentChains::new(parents_bwd, meet, goal),
+                )
+            });
+
+            if fwd.is_exhausted() || bwd.is_exhausted() {
+                self.chains = None;
+                self.meet_idx += 1;
+                continue;
+            }
+
+            // fwd chain is meet->...->start, bwd chain is meet->...->goal
+            let mut path: Vec<u32> = fwd.chain().collect();
+            path.reverse();
+            path.extend(bwd.chain().skip(1));
+
+            if !bwd.advance() && fwd.advance() {
+                *bwd = ParentChains::new(parents_bwd, meet, goal);
+            }
 
-    final_paths
+            if let Some(redirects_passed) = self.redirects_passed {
+                resolve_redirects(&mut path, redirects_passed);
+            }
+            return Some(path);
+        }
+    }
+}
+
+/// Edge between layer i and layer i + 1 of a `LayeredDag`, by position within each layer.
+pub struct DagEdge {
+    pub from: usize,
+    pub to: usize,
+    // redirect page the link went through, if any
+    pub redirect: Option<u32>,
+}
+
+/// `layers[i]` holds the nodes at distance i from start, `edges[i]` connects layer i to i + 1.
+pub struct LayeredDag {
+    pub layers: Vec<Vec<u32>>,
+    pub edges: Vec<Vec<DagEdge>>,
+}
+
+/// Shortest-path subgraph found by `bi_bfs_csr`: both parent DAGs and the meet nodes.
+/// Paths are enumerated lazily and counted without enumerating them.
+pub struct ShortestPathDag {
+    pub start: u32,
+    pub goal: u32,
+    // number of edges in every shortest path
+    pub depth: u8,
+    pub meet_nodes: Vec<u32>,
+    pub parents_fwd: FxHashMap<u32, Vec<u32>>,
+    pub parents_bwd: FxHashMap<u32, Vec<u32>>,
+}
+
+impl ShortestPathDag {
+    /// Number of nodes in every shortest path
+    pub fn path_length(&self) -> usize {
+        self.depth as usize + 1
+    }
+
+    /// Exact number of shortest paths (saturating), via DP over the parent maps
+    pub fn num_paths(&self) -> u64 {
+        let mut memo_fwd: FxHashMap<u32, u64> = FxHashMap::default();
+        let mut memo_bwd: FxHashMap<u32, u64> = FxHashMap::default();
+
+        self.meet_nodes.iter().fold(0u64, |acc, &meet| {
+            let fwd = count_chains(meet, self.start, &self.parents_fwd, &mut memo_fwd);
+            let bwd = count_chains(meet, self.goal, &self.parents_bwd, &mut memo_bwd);
+            acc.saturating_add(fwd.saturating_mul(bwd))
+        })
+    }
+
+    /// Iterate the shortest paths with redirects resolved
+    pub fn paths<'a>(&'a self, redirects_passed: &'a RedirectsPassedMmap) -> PathIter<'a> {
+        PathIter::new(
+            self.start,
+            &self.meet_nodes,
+            self.goal,
+            &self.parents_fwd,
+            &self.parents_bwd,
+            Some(redirects_passed),
+        )
+    }
+
+    /// The shortest-path subgraph as layers (layer i = nodes at distance i from start)
+    /// and the edges between adjacent layers, with the redirect passed on each edge.
+    pub fn layered(&self, redirects_passed: &RedirectsPassedMmap) -> LayeredDag {
+        let depth = self.depth as usize;
+
+        // every meet node sits at the same forward depth, walk first parents to find it
+        let mut meet_depth = 0;
+        let mut node = self.meet_nodes[0];
+        while node != self.start {
+            node = self.parents_fwd[&node][0];
+            meet_depth += 1;
+        }
+
+        let mut layer_of: FxHashMap<u32, usize> = FxHashMap::default();
+        let mut dag_edges: FxHashSet<(u32, u32)> = FxHashSet::default();
+
+        // forward half: meet -> start, edges point from parent to child
+        let mut stack: Vec<(u32, usize)> = Vec::new();
+        for &meet in &self.meet_nodes {
+            if layer_of.insert(meet, meet_depth).is_none() {
+                stack.push((meet, meet_depth));
+            }
+        }
+        while let Some((node, layer)) = stack.pop() {
+            for &p in self.parents_fwd.get(&node).into_iter().flatten() {
+                dag_edges.insert((p, node));
+                if let Entry::Vacant(e) = layer_of.entry(p) {
+                    e.insert(layer - 1);
+                    stack.push((p, layer - 1));
+                }
+            }
+        }
+
+        // backward half: meet -> goal, parents_bwd holds reverse edges so flip them back
+        let mut visited_bwd: FxHashSet<u32> = self.meet_nodes.iter().copied().collect();
+        stack.extend(self.meet_nodes.iter().map(|&meet| (meet, meet_depth)));
+        while let Some((node, layer)) = stack.pop() {
+            for &child in self.parents_bwd.get(&node).into_iter().flatten() {
+                dag_edges.insert((node, child));
+                if visited_bwd.insert(child) {
+                    layer_of.entry(child).or_insert(layer + 1);
+                    stack.push((child, layer + 1));
+                }
+            }
+        }
+
+        let mut layers: Vec<Vec<u32>> = vec![Vec::new(); depth + 1];
+        for (&node, &layer) in &layer_of {
+            layers[layer].push(node);
+        }
+        for layer in &mut layers {
+            layer.sort_unstable();
+        }
+
+        // position of each node within its layer
+        let index_of: FxHashMap<u32, usize> = layers
+            .iter()
+            .flat_map(|layer| layer.iter().enumerate().map(|(i, &node)| (node, i)))
+            .collect();
+
+        let mut edges: Vec<Vec<DagEdge>> = (0..depth).map(|_| Vec::new()).collect();
+        for (from, to) in dag_edges {
+            edges[layer_of[&from]].push(DagEdge {
+                from: index_of[&from],
+                to: index_of[&to],
+                redirect: redirects_passed.get(from, to),
+            });
+        }
+        for layer_edges in &mut edges {
+            layer_edges.sort_unstable_by_key(|e| (e.from, e.to));
+        }
+
+        LayeredDag { layers, edges }
+    }
+}
+
+/// Shortest paths through waypoints, one `ShortestPathDag` per segment
+/// (start -> via[0], via[0] -> via[1], ..., -> goal). Any mix of segment paths is a path.
+pub struct WaypointPaths {
+    pub segments: Vec<ShortestPathDag>,
+}
+
+impl WaypointPaths {
+    /// Number of nodes in every path, waypoints counted once
+    pub fn path_length(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| segment.path_length() - 1)
+            .sum::<usize>()
+            + 1
+    }
+
+    /// Exact number of paths (saturating), the product of the segment counts
+    pub fn num_paths(&self) -> u64 {
+        self.segments
+            .iter()
+            .fold(1u64, |acc, segment| acc.saturating_mul(segment.num_paths()))
+    }
+
+    /// Every node on any of the paths, before resolving redirects
+    pub fn nodes(&self) -> FxHashSet<u32> {
+        let mut nodes = FxHashSet::default();
+        for segment in &self.segments {
+            nodes.extend(&segment.meet_nodes);
+            for parents in [&segment.parents_fwd, &segment.parents_bwd] {
+                for (&node, node_parents) in parents {
+                    nodes.insert(node);
+                    nodes.extend(node_parents);
+                }
+            }
+        }
+        nodes
+    }
+
+    /// Iterate the paths with redirects resolved
+    pub fn paths<'a>(&'a self, redirects_passed: &'a RedirectsPassedMmap) -> WaypointPathIter<'a> {
+        WaypointPathIter {
+            segments: &self.segments,
+            redirects_passed,
+            iters: Vec::new(),
+            current: Vec::new(),
+            sought: false,
+            done: false,
+        }
+    }
+
+    /// Segment layers back to back, a waypoint is the last layer of one segment and the first of the next
+    pub fn layered(&self, redirects_passed: &RedirectsPassedMmap) -> LayeredDag {
+        let mut layers = Vec::new();
+        let mut edges = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            let segment = segment.layered(redirects_passed);
+            layers.extend(segment.layers.into_iter().skip(usize::from(i > 0)));
+            edges.extend(segment.edges);
+        }
+        LayeredDag { layers, edges }
+    }
+}
+
+/// Odometer over the segments' paths, the last segment changes fastest
+pub struct WaypointPathIter<'a> {
+    segments: &'a [ShortestPathDag],
+    redirects_passed: &'a RedirectsPassedMmap,
+    // unresolved paths, redirects are resolved once the segments are joined
+    iters: Vec<PathIter<'a>>,
+    current: Vec<Vec<u32>>,
+    // current is the next path to hand out, not the last one
+    sought: bool,
+    done: bool,
+}
+
+impl WaypointPathIter<'_> {
+    /// Jump to path number `index`, each segment seeks to its digit of it
+    pub fn seek(&mut self, mut index: u64) {
+        let mut digits = vec![0; self.segments.len()];
+        for (digit, segment) in digits.iter_mut().zip(self.segments).rev() {
+            let count = segment.num_paths();
+            if count == 0 {
+                self.done = true;
+                return;
+            }
+            *digit = index % count;
+            index /= count;
+        }
+        // past the last path
+        if index > 0 {
+            self.done = true;
+            return;
+        }
+
+        self.iters.clear();
+        self.current.clear();
+        for (segment, digit) in self.segments.iter().zip(digits) {
+            let mut iter = unresolved_paths(segment);
+            iter.seek(digit);
+            let Some(path) = iter.next() else {
+                self.done = true;
+                return;
+            };
+            self.iters.push(iter);
+            self.current.push(path);
+        }
+        self.sought = true;
+    }
+}
+
+fn unresolved_paths(segment: &ShortestPathDag) -> PathIter<'_> {
+    PathIter::new(
+        segment.start,
+        &segment.meet_nodes,
+        segment.goal,
+        &segment.parents_fwd,
+        &segment.parents_bwd,
+        None,
+    )
+}
+
+impl Iterator for WaypointPathIter<'_> {
+    type Item = Vec<u32>;
+
+    fn next(&mut self) -> Option<Vec<u32>> {
+        if self.done {
+            return None;
+        }
+
+        if self.current.is_empty() {
+            // first path: first path of every segment
+            self.seek(0);
+            if self.done {
+                return None;
+            }
+        }
+        if self.sought {
+            self.sought = false;
+        } else {
+            let mut i = self.segments.len();
+            loop {
+                if i == 0 {
+                    self.done = true;
+                    return None;
+                }
+                i -= 1;
+                if let Some(path) = self.iters[i].next() {
+                    self.current[i] = path;
+                    break;
+                }
+                // this segment wrapped around, restart it and move the one before
+                self.iters[i] = unresolved_paths(&self.segments[i]);
+                self.current[i] = self.iters[i].next().unwrap();
+            }
+        }
+
+        let mut path = self.current[0].clone();
+        for segment_path in &self.current[1..] {
+            path.extend_from_slice(&segment_path[1..]);
+        }
+        resolve_redirects(&mut path, self.redirects_passed);
+        Some(path)
+    }
 }
 
 pub fn bfs_csr<G>(
     graph: &G,
     orig_start: u32,
     orig_goal: u32,
-    max_depth: u8,
+    limits: &SearchLimits,
     backwards: bool,
     redirects_passed: &RedirectsPassedMmap,
     // redirects_passed: &FxHashMap<(u32, u32), u32>,
-) -> Option<Vec<Vec<u32>>>
+) -> SearchOutcome<Vec<Vec<u32>>>
 where
     G: CsrGraphTrait,
 {
@@ -424,7 +982,7 @@ where
 
     // case where start is same as goal (can happen when the start is a redirect to the goal)
     if start == goal {
-        return Some(vec![vec![start]]);
+        return SearchOutcome::Found(vec![vec![start]]);
     }
 
     let mut queue = VecDeque::new();
@@ -438,6 +996,7 @@ where
 
     let mut goal_found_at_depth: Option<u8> = None;
     let mut depth = 0;
+    let mut node_count: u32 = 0;
 
     while !queue.is_empty() {
         // If we have already found the goal, finish this depth and then stop
@@ -448,9 +1007,9 @@ where
         }
 
         // Check max depth
-        if depth >= max_depth {
+        if depth >= limits.max_depth {
             println!("MAX DEPTH REACHED");
-            return None;
+            return SearchOutcome::Exhausted(LimitHit::MaxDepth);
         }
 
         depth += 1;
@@ -460,14 +1019,18 @@ where
         for _ in 0..level_size {
             let (node, current_depth) = queue.pop_front().unwrap();
             let next_depth = current_depth + 1;
+            node_count += 1;
+            if let Err(limit) = limits.check(node_count) {
+                return SearchOutcome::Exhausted(limit);
+            }
 
             let neighbors = if backwards {
-                graph.get_reverse(node)
+                graph.reverse_neighbors(node)
             } else {
-                graph.get(node)
+                graph.neighbors(node)
             };
 
-            for &neighbor in neighbors {
+            for neighbor in neighbors {
                 process_neighbor(
                     neighbor,
                     next_depth,
@@ -485,27 +1048,350 @@ where
     if goal_found_at_depth.is_some() {
         let elapsed = now.elapsed();
         println!("Elapsed: {:.2?}", elapsed);
-        return Some(reconstruct_all_paths(
+        // a bidirectional search that met at the goal, with nothing on the backward side
+        let no_parents = FxHashMap::default();
+        let paths = PathIter::new(start, &[goal], goal, &parents, &no_parents, None)
+            .map(|mut path| {
+                if backwards {
+                    path.reverse();
+                }
+                resolve_redirects(&mut path, redirects_passed);
+                path
+            })
+            .collect();
+        return SearchOutcome::Found(paths);
+    }
+
+    SearchOutcome::NoPath
+}
+
+pub fn bi_bfs_csr<G>(
+    graph: &G,
+    ctx: &mut SearchContext,
+    start: u32,
+    goal: u32,
+    limits: &SearchLimits,
+    node_count: &mut u32,
+) -> SearchOutcome<ShortestPathDag>
+where
+    G: CsrGraphTrait,
+{
+    bi_bfs_csr_excluding(graph, ctx, start, goal, &[], limits, node_count)
+}
+
+// depth given to excluded nodes, never a real depth so they can't be anyone's parent
+pub const EXCLUDED_DEPTH: u8 = u8::MAX;
+
+/// `bi_bfs_csr` that never goes through the `excluded` nodes.
+/// Start and goal must not be excluded.
+pub fn bi_bfs_csr_excluding<G>(
+    graph: &G,
+    ctx: &mut SearchContext,
+    start: u32,
+    goal: u32,
+    excluded: &[u32],
+    limits: &SearchLimits,
+    node_count: &mut u32,
+) -> SearchOutcome<ShortestPathDag>
+where
+    G: CsrGraphTrait,
+{
+    let now = Instant::now();
+
+    // start and goal should be dense ids, also should not be redirects
+
+    // case where start is same as goal (can happen when the start is a redirect to the goal)
+    if start == goal {
+        return SearchOutcome::Found(ShortestPathDag {
             start,
             goal,
-            &parents,
-            redirects_passed,
-            true,
-            backwards,
-        ));
+            depth: 0,
+            meet_nodes: vec![start],
+            parents_fwd: FxHashMap::default(),
+            parents_bwd: FxHashMap::default(),
+        });
     }
 
-    None
+    ctx.reset();
+    let SearchContext {
+        depth_fwd: visited_depth_fwd,
+        depth_bwd: visited_depth_bwd,
+        frontier_fwd,
+        frontier_bwd,
+        next_frontier,
+        claimed_bits,
+        parallel_threshold,
+        ..
+    } = ctx;
+
+    // excluded nodes look visited to both sides, so they never get expanded or become meet nodes
+    for &node in excluded {
+        visited_depth_fwd.set(node, EXCLUDED_DEPTH);
+        visited_depth_bwd.set(node, EXCLUDED_DEPTH);
+    }
+
+    visited_depth_fwd.set(start, 0);
+    visited_depth_bwd.set(goal, 0);
+
+    frontier_fwd.push(start);
+    frontier_bwd.push(goal);
+
+    let mut meet_nodes: Vec<u32> = Vec::new();
+    let mut meet_found_at_depth: Option<u8> = None;
+
+    let mut depth_fwd = 0;
+    let mut depth_bwd = 0;
+
+    while !frontier_fwd.is_empty() && !frontier_bwd.is_empty() {
+        let combined_depth = depth_fwd + depth_bwd;
+
+        if let Some(meet_depth) = meet_found_at_depth {
+            if combined_depth >= meet_depth {
+                break;
+            }
+        }
+        // Check max depth
+        if combined_depth >= limits.max_depth {
+            tracing::info!("MAX DEPTH REACHED");
+            return SearchOutcome::Exhausted(LimitHit::MaxDepth);
+        }
+
+        let (frontier, visited_depth_this, visited_depth_other, depth, backwards) =
+            if frontier_fwd.len() <= frontier_bwd.len() {
+                (
+                    &mut *frontier_fwd,
+                    &mut *visited_depth_fwd,
+                    &*visited_depth_bwd,
+                    &mut depth_fwd,
+                    false,
+                )
+            } else {
+                (
+                    &mut *frontier_bwd,
+                    &mut *visited_depth_bwd,
+                    &*visited_depth_fwd,
+                    &mut depth_bwd,
+                    true,
+                )
+            };
+
+        *depth += 1;
+        let next_depth = *depth;
+        tracing::debug!(
+            "Depth {} {}",
+            if backwards { "backwards" } else { "forwards" },
+            depth
+        );
+
+        next_frontier.clear();
+        if frontier.len() >= *parallel_threshold {
+            // limits are checked up front, a parallel step can't be stopped halfway
+            for _ in 0..frontier.len() {
+                *node_count += 1;
+                if let Err(limit) = limits.check(*node_count) {
+                    tracing::info!("search stopped ({:?}) after {} nodes", limit, node_count);
+                    return SearchOutcome::Exhausted(limit);
+                }
+            }
+
+            let claimed = expand_frontier_parallel(
+                graph,
+                frontier,
+                visited_depth_this,
+                claimed_bits,
+                backwards,
+            );
+            for neighbor in claimed.into_iter().flatten() {
+                claimed_bits[neighbor as usize / 64].store(0, Ordering::Relaxed);
+                visited_depth_this.set(neighbor, next_depth);
+                next_frontier.push(neighbor);
+            }
+        } else {
+            for &node in frontier.iter() {
+                *node_count += 1;
+                if let Err(limit) = limits.check(*node_count) {
+                    tracing::info!("search stopped ({:?}) after {} nodes", limit, node_count);
+                    return SearchOutcome::Exhausted(limit);
+                }
+
+                let neighbors = if backwards {
+                    graph.reverse_neighbors(node)
+                } else {
+                    graph.neighbors(node)
+                };
+
+                for neighbor in neighbors {
+                    // only first sightings matter, parents get rebuilt from the depths afterwards
+                    if visited_depth_this.insert(neighbor, next_depth) {
+                        next_frontier.push(neighbor);
+                    }
+                }
+            }
+        }
+
+        // check for meeting points
+        for &node in next_frontier.iter() {
+            if visited_depth_other.contains(node) {
+                if meet_found_at_depth.is_none() {
+                    meet_found_at_depth = Some(combined_depth + 1);
+                    tracing::debug!("path found at depth {}", combined_depth + 1);
+                }
+                meet_nodes.push(node);
+            }
+        }
+        std::mem::swap(frontier, next_frontier);
+    }
+
+    if let Some(depth) = meet_found_at_depth {
+        let parents_fwd = collect_parents(graph, visited_depth_fwd, &meet_nodes, false);
+        let parents_bwd = collect_parents(graph, visited_depth_bwd, &meet_nodes, true);
+
+        let elapsed = now.elapsed();
+        tracing::debug!("search done in: {:.2?}", elapsed);
+
+        return SearchOutcome::Found(ShortestPathDag {
+            start,
+            goal,
+            depth,
+            meet_nodes,
+            parents_fwd,
+            parents_bwd,
+        });
+    }
+
+    SearchOutcome::NoPath
 }
 
-pub fn bi_bfs_csr<G>(
+// smallest slice of a frontier worth handing to another thread
+const MIN_PARALLEL_CHUNK: usize = 64;
+
+/// Neighbours found by one level of `bi_bfs_csr_excluding` with the frontier split across
+/// threads. A neighbour goes to whichever chunk sets its bit in `claimed_bits` first, so the
+/// new nodes are the same as the sequential loop finds, only their order can differ.
+/// The caller sets their depths and clears the bits.
+fn expand_frontier_parallel<G>(
+    graph: &G,
+    frontier: &[u32],
+    visited_depth: &StampedDepths,
+    claimed_bits: &[AtomicU64],
+    backwards: bool,
+) -> Vec<Vec<u32>>
+where
+    G: CsrGraphTrait,
+{
+    // several chunks per thread, so a chunk with a huge page in it doesn't hold up the level
+    let chunk_size = (frontier.len() / (rayon::current_num_threads() * 8)).max(MIN_PARALLEL_CHUNK);
+    frontier
+        .par_chunks(chunk_size)
+        .map(|chunk| {
+            let mut claimed = Vec::new();
+            for &node in chunk {
+                let neighbors = if backwards {
+                    graph.reverse_neighbors(node)
+                } else {
+                    graph.neighbors(node)
+                };
+                for neighbor in neighbors {
+                    if visited_depth.contains(neighbor) {
+                        continue;
+                    }
+                    let bit = 1u64 << (neighbor % 64);
+                    let word = &claimed_bits[neighbor as usize / 64];
+                    // a plain load first, most neighbours of a big frontier are already claimed
+                    if word.load(Ordering::Relaxed) & bit == 0
+                        && word.fetch_or(bit, Ordering::Relaxed) & bit == 0
+                    {
+                        claimed.push(neighbor);
+                    }
+                }
+            }
+            claimed
+        })
+        .collect()
+}
+
+/// Shortest paths start -> via[0] -> ... -> goal that avoid the `excluded` nodes, one search per segment.
+/// Segments are searched independently, so a path can visit a page twice. `max_depth` is per segment.
+pub fn bi_bfs_csr_via<G>(
+    searcher: &mut Searcher<G>,
+    start: u32,
+    via: &[u32],
+    goal: u32,
+    excluded: &[u32],
+) -> SearchOutcome<WaypointPaths>
+where
+    G: CsrGraphTrait,
+{
+    let mut stops = Vec::with_capacity(via.len() + 2);
+    stops.push(start);
+    stops.extend_from_slice(via);
+    stops.push(goal);
+
+    let mut segments = Vec::with_capacity(stops.len() - 1);
+    for pair in stops.windows(2) {
+        match bi_bfs_csr_excluding(
+            searcher.graph,
+            searcher.ctx,
+            pair[0],
+            pair[1],
+            excluded,
+            searcher.limits,
+            &mut searcher.node_count,
+        ) {
+            SearchOutcome::Found(segment) => segments.push(segment),
+            SearchOutcome::NoPath => return SearchOutcome::NoPath,
+            SearchOutcome::Exhausted(limit) => return SearchOutcome::Exhausted(limit),
+        }
+    }
+    SearchOutcome::Found(WaypointPaths { segments })
+}
+
+/// Rebuild one side's parent DAG from the depths a search left behind, only for the nodes
+/// on shortest paths: a node's parents are its neighbours one level closer to the root.
+fn collect_parents<G>(
+    graph: &G,
+    visited_depth: &StampedDepths,
+    meet_nodes: &[u32],
+    backwards: bool,
+) -> FxHashMap<u32, Vec<u32>>
+where
+    G: CsrGraphTrait,
+{
+    let mut parents: FxHashMap<u32, Vec<u32>> = FxHashMap::default();
+    let mut stack: Vec<u32> = meet_nodes.to_vec();
+
+    while let Some(node) = stack.pop() {
+        let depth = visited_depth.get(node).unwrap();
+        if depth == 0 || parents.contains_key(&node) {
+            continue;
+        }
+
+        // parents link to the node, so look at the edges going the other way
+        let candidates = if backwards {
+            graph.neighbors(node)
+        } else {
+            graph.reverse_neighbors(node)
+        };
+        let node_parents: Vec<u32> = candidates
+            .filter(|&p| visited_depth.get(p) == Some(depth - 1))
+            .collect();
+
+        stack.extend_from_slice(&node_parents);
+        parents.insert(node, node_parents);
+    }
+
+    parents
+}
+
+/// Same search as `bi_bfs_csr` with fresh hashmaps per search and parents recorded as it goes.
+/// Kept to compare against in `benchmark_random_bfs`.
+pub fn bi_bfs_csr_hashmap<G>(
     graph: &G,
     start: u32,
     goal: u32,
     max_depth: u8,
-    redirects_passed: &RedirectsPassedMmap,
-    node_count: &mut u32, // redirects_passed: &FxHashMap<(u32, u32), u32>,
-) -> Option<Vec<Vec<u32>>>
+    node_count: &mut u32,
+) -> Option<ShortestPathDag>
 where
     G: CsrGraphTrait,
 {
@@ -515,7 +1401,14 @@ where
 
     // case where start is same as goal (can happen when the start is a redirect to the goal)
     if start == goal {
-        return Some(vec![vec![start]]);
+        return Some(ShortestPathDag {
+            start,
+            goal,
+            depth: 0,
+            meet_nodes: vec![start],
+            parents_fwd: FxHashMap::default(),
+            parents_bwd: FxHashMap::default(),
+        });
     }
 
     let mut queue_fwd = VecDeque::new();
@@ -587,12 +1480,12 @@ where
             let next_combined_depth = combined_depth + 1;
 
             let neighbors = if backwards {
-                graph.get_reverse(node)
+                graph.reverse_neighbors(node)
             } else {
-                graph.get(node)
+                graph.neighbors(node)
             };
 
-            for &neighbor in neighbors {
+            for neighbor in neighbors {
                 process_neighbor_bi(
                     neighbor,
                     next_depth,
@@ -609,92 +1502,589 @@ where
         }
     }
 
-    if meet_found_at_depth.is_some() {
+    if let Some(depth) = meet_found_at_depth {
         let elapsed = now.elapsed();
         tracing::debug!("search done in: {:.2?}", elapsed);
 
-        return Some(merge_all_paths(
+        return Some(ShortestPathDag {
             start,
-            &meet_nodes,
             goal,
-            &parents_fwd,
-            &parents_bwd,
-            redirects_passed,
-            true,
-        ));
+            depth,
+            meet_nodes,
+            parents_fwd,
+            parents_bwd,
+        });
     }
 
     None
 }
 
-pub fn bi_bfs_csr_depth_only<G>(graph: &G, start: u32, goal: u32) -> Option<u8>
+// direction-optimizing bfs thresholds from Beamer et al.
+// go bottom-up once the frontier's edges are more than 1/ALPHA of the unexplored edges,
+// back to top-down once the frontier is smaller than 1/BETA of the nodes
+const BOTTOM_UP_ALPHA: usize = 14;
+const BOTTOM_UP_BETA: usize = 24;
+
+/// Bidirectional search that only returns the distance. A side whose frontier gets big
+/// switches to bottom-up steps: instead of expanding the frontier, every node it hasn't
+/// reached checks if any node linking to it is in the frontier.
+/// With landmark `bounds` it can stop a level early once only the upper bound is left.
+pub fn bi_bfs_csr_depth_only<G>(
+    graph: &G,
+    ctx: &mut SearchContext,
+    start: u32,
+    goal: u32,
+    limits: &SearchLimits,
+    bounds: Option<DistanceBounds>,
+) -> SearchOutcome<u8>
 where
     G: CsrGraphTrait,
 {
     if start == goal {
-        return Some(0);
+        return SearchOutcome::Found(0);
+    }
+    // landmark bounds can settle it without a search
+    let upper = bounds.and_then(|bounds| bounds.upper);
+    if let Some(bounds) = bounds {
+        if bounds.lower > limits.max_depth {
+            return SearchOutcome::Exhausted(LimitHit::MaxDepth);
+        }
+        if bounds.is_exact() {
+            return SearchOutcome::Found(bounds.lower);
+        }
     }
 
-    let mut visited_fwd: FxHashSet<u32> = FxHashSet::default();
-    let mut visited_bwd: FxHashSet<u32> = FxHashSet::default();
-
-    let mut queue_fwd: VecDeque<u32> = VecDeque::new();
-    let mut queue_bwd: VecDeque<u32> = VecDeque::new();
+    ctx.reset();
+    let SearchContext {
+        depth_fwd: visited_fwd,
+        depth_bwd: visited_bwd,
+        frontier_fwd: queue_fwd,
+        frontier_bwd: queue_bwd,
+        next_frontier,
+        frontier_bits,
+        ..
+    } = ctx;
 
-    visited_fwd.insert(start);
-    visited_bwd.insert(goal);
+    visited_fwd.set(start, 0);
+    visited_bwd.set(goal, 0);
 
-    queue_fwd.push_back(start);
-    queue_bwd.push_back(goal);
+    queue_fwd.push(start);
+    queue_bwd.push(goal);
 
     let mut depth_fwd: u8 = 0;
     let mut depth_bwd: u8 = 0;
 
+    let num_nodes = graph.num_nodes();
+    let num_edges = graph.num_edges();
+    let mut edges_explored_fwd: usize = 0;
+    let mut edges_explored_bwd: usize = 0;
+    let mut bottom_up_fwd = false;
+    let mut bottom_up_bwd = false;
+    // bottom-up steps count every node they look at, not just the frontier
+    let mut node_count: u32 = 0;
+
     while !queue_fwd.is_empty() && !queue_bwd.is_empty() {
+        if depth_fwd + depth_bwd >= limits.max_depth {
+            return SearchOutcome::Exhausted(LimitHit::MaxDepth);
+        }
+        // nothing met within depth_fwd + depth_bwd, so a known route one longer is the shortest
+        if let Some(upper) = upper.filter(|&upper| depth_fwd + depth_bwd + 1 >= upper) {
+            return SearchOutcome::Found(upper);
+        }
+
         // pick the smaller frontier to expand
-        let (queue, visited_this, visited_other, depth, backwards) =
+        let (queue, visited_this, visited_other, depth, edges_explored, bottom_up, backwards) =
             if queue_fwd.len() <= queue_bwd.len() {
                 (
-                    &mut queue_fwd,
-                    &mut visited_fwd,
-                    &visited_bwd,
+                    &mut *queue_fwd,
+                    &mut *visited_fwd,
+                    &*visited_bwd,
                     &mut depth_fwd,
+                    &mut edges_explored_fwd,
+                    &mut bottom_up_fwd,
                     false,
                 )
             } else {
                 (
-                    &mut queue_bwd,
-                    &mut visited_bwd,
-                    &visited_fwd,
+                    &mut *queue_bwd,
+                    &mut *visited_bwd,
+                    &*visited_fwd,
                     &mut depth_bwd,
+                    &mut edges_explored_bwd,
+                    &mut bottom_up_bwd,
                     true,
                 )
             };
 
         *depth += 1;
-        let level_size = queue.len();
+        let next_depth = *depth;
+
+        // edges a top-down step would scan
+        let frontier_edges: usize = queue
+            .iter()
+            .map(|&node| {
+                if backwards {
+                    graph.reverse_degree(node)
+                } else {
+                    graph.degree(node)
+                }
+            })
+            .sum();
+        let unexplored_edges = num_edges.saturating_sub(*edges_explored);
+        *edges_explored += frontier_edges;
+        *bottom_up = if *bottom_up {
+            queue.len() >= num_nodes / BOTTOM_UP_BETA
+        } else {
+            frontier_edges > unexplored_edges / BOTTOM_UP_ALPHA
+        };
+
+        next_frontier.clear();
+        let step = Step {
+            visited_this,
+            visited_other,
+            next_frontier: &mut *next_frontier,
+            next_depth,
+            backwards,
+        };
+        let met = if *bottom_up {
+            bottom_up_step(graph, queue, frontier_bits, step, limits, &mut node_count)
+        } else {
+            top_down_step(graph, queue, step, limits, &mut node_count)
+        };
+        match met {
+            // first meeting point found
+            Ok(true) => return SearchOutcome::Found(depth_fwd + depth_bwd),
+            Ok(false) => {}
+            Err(limit) => return SearchOutcome::Exhausted(limit),
+        }
+        std::mem::swap(queue, next_frontier);
+    }
+
+    SearchOutcome::NoPath
+}
+
+/// The side of a depth-only search taking a step, and the other side it's looking for
+struct Step<'a> {
+    visited_this: &'a mut StampedDepths,
+    visited_other: &'a StampedDepths,
+    next_frontier: &'a mut Vec<u32>,
+    next_depth: u8,
+    backwards: bool,
+}
+
+/// Expand every frontier node's edges. Returns true as soon as the other side is reached.
+fn top_down_step<G>(
+    graph: &G,
+    frontier: &[u32],
+    step: Step,
+    limits: &SearchLimits,
+    node_count: &mut u32,
+) -> Result<bool, LimitHit>
+where
+    G: CsrGraphTrait,
+{
+    let Step {
+        visited_this,
+        visited_other,
+        next_frontier,
+        next_depth,
+        backwards,
+    } = step;
+    for &node in frontier {
+        *node_count += 1;
+        limits.check(*node_count)?;
+
+        let neighbors = if backwards {
+            graph.reverse_neighbors(node)
+        } else {
+            graph.neighbors(node)
+        };
+
+        for neighbor in neighbors {
+            if visited_other.contains(neighbor) {
+                return Ok(true);
+            }
+            if visited_this.insert(neighbor, next_depth) {
+                next_frontier.push(neighbor);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Every node not reached yet looks for a frontier node among the nodes linking to it
+/// (in this side's direction), stopping at the first one. Returns true as soon as the other side is reached.
+fn bottom_up_step<G>(
+    graph: &G,
+    frontier: &[u32],
+    frontier_bits: &mut [u64],
+    step: Step,
+    limits: &SearchLimits,
+    node_count: &mut u32,
+) -> Result<bool, LimitHit>
+where
+    G: CsrGraphTrait,
+{
+    let Step {
+        visited_this,
+        visited_other,
+        next_frontier,
+        next_depth,
+        backwards,
+    } = step;
+    for &node in frontier {
+        frontier_bits[node as usize / 64] |= 1 << (node % 64);
+    }
+    let in_frontier = |node: u32| frontier_bits[node as usize / 64] >> (node % 64) & 1 == 1;
+
+    let mut met = Ok(false);
+    for node in 0..graph.num_nodes() as u32 {
+        if visited_this.contains(node) {
+            continue;
+        }
+        *node_count += 1;
+        if let Err(limit) = limits.check(*node_count) {
+            met = Err(limit);
+            break;
+        }
+        let mut parents = if backwards {
+            graph.neighbors(node)
+        } else {
+            graph.reverse_neighbors(node)
+        };
+        if parents.any(in_frontier) {
+            if visited_other.contains(node) {
+                met = Ok(true);
+                break;
+            }
+            visited_this.set(node, next_depth);
+            next_frontier.push(node);
+        }
+    }
+
+    // only frontier bits were set, so zeroing their words leaves the bitset empty
+    for &node in frontier {
+        frontier_bits[node as usize / 64] = 0;
+    }
+    met
+}
+
+/// How many pages are at each distance from one page
+pub struct DistanceHistogram {
+    // counts[d] = pages at distance d, counts[0] is the page itself
+    pub counts: Vec<u32>,
+    // some of the pages at the largest distance, lowest dense ids first
+    pub farthest: Vec<u32>,
+}
+
+impl DistanceHistogram {
+    /// Largest distance to a reachable page
+    pub fn eccentricity(&self) -> usize {
+        self.counts.len() - 1
+    }
+
+    /// Pages reachable, the source included
+    pub fn reachable(&self) -> u64 {
+        self.counts.iter().map(|&count| count as u64).sum()
+    }
+}
+
+/// Full bfs from `source` (following links backwards if `backwards`), counting pages per distance.
+/// Never `NoPath`, a page with no links just has a histogram of one.
+pub fn distance_histogram<G>(
+    graph: &G,
+    ctx: &mut SearchContext,
+    source: u32,
+    backwards: bool,
+    num_farthest: usize,
+    limits: &SearchLimits,
+    node_count: &mut u32,
+) -> SearchOutcome<DistanceHistogram>
+where
+    G: CsrGraphTrait,
+{
+    ctx.reset();
+    let SearchContext {
+        depth_fwd: visited_depth,
+        frontier_fwd: frontier,
+        next_frontier,
+        ..
+    } = ctx;
+
+    visited_depth.set(source, 0);
+    frontier.push(source);
+    let mut counts: Vec<u32> = vec![1];
+    let mut depth: u8 = 0;
+
+    loop {
+        next_frontier.clear();
+        for &node in frontier.iter() {
+            *node_count += 1;
+            if let Err(limit) = limits.check(*node_count) {
+                return SearchOutcome::Exhausted(limit);
+            }
 
-        for _ in 0..level_size {
-            let node = queue.pop_front().unwrap();
             let neighbors = if backwards {
-                graph.get_reverse(node)
+                graph.reverse_neighbors(node)
             } else {
-                graph.get(node)
+                graph.neighbors(node)
             };
-
-            for &neighbor in neighbors {
-                if visited_other.contains(&neighbor) {
-                    // first meeting point found
-                    return Some(depth_fwd + depth_bwd);
+            for neighbor in neighbors {
+                if visited_depth.insert(neighbor, depth.saturating_add(1)) {
+                    next_frontier.push(neighbor);
                 }
-                if visited_this.insert(neighbor) {
-                    queue.push_back(neighbor);
+            }
+        }
+
+        // the current frontier is the farthest level
+        if next_frontier.is_empty() {
+            break;
+        }
+        if depth >= limits.max_depth {
+            return SearchOutcome::Exhausted(LimitHit::MaxDepth);
+        }
+        depth += 1;
+        counts.push(next_frontier.len() as u32);
+        std::mem::swap(frontier, next_frontier);
+    }
+
+    let mut farthest = frontier.clone();
+    farthest.sort_unstable();
+    farthest.truncate(num_farthest);
+
+    SearchOutcome::Found(DistanceHistogram { counts, farthest })
+}
+
+/// Print how many pages are at each distance from a title, and some of the farthest ones
+pub fn print_distance_histogram(
+    data: &DataDir,
+    title: &str,
+    backwards: bool,
+) -> anyhow::Result<()> {
+    let csr_graph = load_csr_graph_mmap(data)?;
+    let title_to_dense_id = load_title_to_dense_id_mmap(data)?;
+    let dense_id_to_title = load_dense_id_to_title_mmap(data)?;
+    let redirect_targets_dense = load_redirect_targets_dense_mmap(data)?;
+
+    let source = title_to_dense_id
+        .get(title)
+        .ok_or_else(|| anyhow::anyhow!("title '{}' not found", title))?;
+    let source = match redirect_targets_dense.get(source) {
+        u32::MAX => source,
+        redirect => redirect,
+    };
+
+    let now = Instant::now();
+    let mut ctx = SearchContext::new(csr_graph.num_nodes());
+    let mut node_count = 0;
+    let histogram = match distance_histogram(
+        &csr_graph,
+        &mut ctx,
+        source,
+        backwards,
+        20,
+        &SearchLimits::default(),
+        &mut node_count,
+    ) {
+        SearchOutcome::Found(histogram) => histogram,
+        SearchOutcome::NoPath => unreachable!(),
+        SearchOutcome::Exhausted(limit) => anyhow::bail!("bfs stopped by {:?}", limit),
+    };
+
+    println!(
+        "Distances {} {} ({:.2?}):",
+        if backwards { "to" } else { "from" },
+        dense_id_to_title.get(source),
+        now.elapsed()
+    );
+    for (distance, count) in histogram.counts.iter().enumerate() {
+        println!("  {:>3}: {}", distance, count);
+    }
+    println!(
+        "Reachable: {} of {} pages, eccentricity {}",
+        histogram.reachable(),
+        csr_graph.num_nodes(),
+        histogram.eccentricity()
+    );
+    println!("Farthest pages:");
+    for &id in &histogram.farthest {
+        println!("  {}", dense_id_to_title.get(id));
+    }
+    Ok(())
+}
+
+/// Distance to one target of `multi_target_distances`, with one shortest path as a witness
+pub struct TargetDistance {
+    pub distance: u8,
+    // source -> target, or target -> source when searching backwards
+    pub path: Vec<u32>,
+}
+
+/// Bfs from `source` that stops as soon as every target is reached, instead of covering the graph.
+/// With `backwards` links are followed backwards, so it's the distance from each target to the source.
+/// Results are in the order of `targets`, none for targets that can't be reached. Never `NoPath`.
+pub fn multi_target_distances<G>(
+    graph: &G,
+    ctx: &mut SearchContext,
+    source: u32,
+    targets: &[u32],
+    backwards: bool,
+    limits: &SearchLimits,
+    node_count: &mut u32,
+) -> SearchOutcome<Vec<Option<TargetDistance>>>
+where
+    G: CsrGraphTrait,
+{
+    ctx.reset();
+    let SearchContext {
+        depth_fwd: visited_depth,
+        frontier_fwd: frontier,
+        next_frontier,
+        ..
+    } = ctx;
+
+    let mut pending: FxHashSet<u32> = targets.iter().copied().collect();
+    pending.remove(&source);
+    visited_depth.set(source, 0);
+    frontier.push(source);
+    let mut depth: u8 = 0;
+
+    while !pending.is_empty() && !frontier.is_empty() {
+        if depth >= limits.max_depth {
+            return SearchOutcome::Exhausted(LimitHit::MaxDepth);
+        }
+        depth += 1;
+        next_frontier.clear();
+        for &node in frontier.iter() {
+            *node_count += 1;
+            if let Err(limit) = limits.check(*node_count) {
+                return SearchOutcome::Exhausted(limit);
+            }
+
+            let neighbors = if backwards {
+                graph.reverse_neighbors(node)
+            } else {
+                graph.neighbors(node)
+            };
+            for neighbor in neighbors {
+                if visited_depth.insert(neighbor, depth) {
+                    pending.remove(&neighbor);
+                    next_frontier.push(neighbor);
                 }
             }
         }
+        std::mem::swap(frontier, next_frontier);
     }
 
-    None
+    // walk back from each target through neighbours one level closer to the source,
+    // lowest dense id first so the witness doesn't change between runs
+    let witness = |target: u32, distance: u8| -> Vec<u32> {
+        let mut path = Vec::with_capacity(distance as usize + 1);
+        path.push(target);
+        let mut node = target;
+        for level in (0..distance).rev() {
+            let mut closer = if backwards {
+                graph.neighbors(node)
+            } else {
+                graph.reverse_neighbors(node)
+            };
+            node = closer
+                .find(|&prev| visited_depth.get(prev) == Some(level))
+                .expect("a node at depth d has a neighbour at depth d - 1");
+            path.push(node);
+        }
+        if !backwards {
+            path.reverse();
+        }
+        path
+    };
+
+    let distances = targets
+        .iter()
+        .map(|&target| {
+            visited_depth.get(target).map(|distance| TargetDistance {
+                distance,
+                path: witness(target, distance),
+            })
+        })
+        .collect();
+    SearchOutcome::Found(distances)
+}
+
+/// Print the distance and a shortest path from a title to every title in a file (one per line),
+/// or from each of them to the title with `backwards`
+pub fn print_target_distances(
+    data: &DataDir,
+    title: &str,
+    targets_path: &str,
+    backwards: bool,
+) -> anyhow::Result<()> {
+    let csr_graph = load_csr_graph_mmap(data)?;
+    let title_to_dense_id = load_title_to_dense_id_mmap(data)?;
+    let dense_id_to_title = load_dense_id_to_title_mmap(data)?;
+    let redirect_targets_dense = load_redirect_targets_dense_mmap(data)?;
+    let redirects_passed = load_redirects_passed_mmap(data)?;
+
+    let resolve = |title: &str| {
+        title_to_dense_id
+            .get(title)
+            .map(|dense_id| match redirect_targets_dense.get(dense_id) {
+                u32::MAX => dense_id,
+                redirect => redirect,
+            })
+    };
+    let source = resolve(title).ok_or_else(|| anyhow::anyhow!("title '{}' not found", title))?;
+
+    let target_titles: Vec<String> = std::fs::read_to_string(targets_path)?
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    // titles that don't exist are reported, not searched
+    let resolved: Vec<Option<u32>> = target_titles.iter().map(|title| resolve(title)).collect();
+    let targets: Vec<u32> = resolved.iter().flatten().copied().collect();
+
+    let now = Instant::now();
+    let mut ctx = SearchContext::new(csr_graph.num_nodes());
+    let mut node_count = 0;
+    let distances = match multi_target_distances(
+        &csr_graph,
+        &mut ctx,
+        source,
+        &targets,
+        backwards,
+        &SearchLimits::default(),
+        &mut node_count,
+    ) {
+        SearchOutcome::Found(distances) => distances,
+        SearchOutcome::NoPath => unreachable!(),
+        SearchOutcome::Exhausted(limit) => anyhow::bail!("bfs stopped by {:?}", limit),
+    };
+
+    println!(
+        "Distances {} {} to {} targets ({:.2?}, {} pages expanded):",
+        if backwards { "to" } else { "from" },
+        dense_id_to_title.get(source),
+        targets.len(),
+        now.elapsed(),
+        node_count
+    );
+    let mut distances = distances.into_iter();
+    for (target_title, target) in target_titles.iter().zip(&resolved) {
+        if target.is_none() {
+            println!("  {}: not found", target_title);
+            continue;
+        }
+        match distances.next().flatten() {
+            Some(TargetDistance { distance, mut path }) => {
+                resolve_redirects(&mut path, &redirects_passed);
+                let titles: Vec<&str> = path.iter().map(|&id| dense_id_to_title.get(id)).collect();
+                println!("  {}: {} ({})", target_title, distance, titles.join(" -> "));
+            }
+            None => println!("  {}: unreachable", target_title),
+        }
+    }
+    Ok(())
 }
 
 use std::collections::HashSet;
@@ -747,6 +2137,7 @@ pub fn bfs_interactive_session<G>(
 {
     let stdin = io::stdin();
     let mut stdout = io::stdout();
+    let mut ctx = SearchContext::new(csr_graph.num_nodes());
 
     loop {
         print!("Enter start page title (or 'exit'): ");
@@ -985,32 +2376,40 @@ pub fn bfs_interactive_session<G>(
         println!("\nRunning bidirectional BFS on CSR graph...");
         let now = Instant::now();
         let mut node_count = 0;
-        let paths_adj_fwd = bi_bfs_csr(
+        let dag = bi_bfs_csr(
             csr_graph,
+            &mut ctx,
             start_id,
             goal_id,
-            max_depth,
-            redirects_passed,
+            &SearchLimits::with_max_depth(max_depth),
             &mut node_count,
         );
         let elapsed_fwd = now.elapsed();
 
-        match &paths_adj_fwd {
-            Some(paths) if !paths.is_empty() => {
+        match &dag {
+            SearchOutcome::Found(dag) => {
                 println!(
                     "Paths found (csr) [{} shortest paths, {:.2?}]:",
-                    paths.len(),
+                    dag.num_paths(),
                     elapsed_fwd
                 );
-                let path = &paths[0];
+                let path = dag.paths(redirects_passed).next().unwrap();
                 println!("Path 1 ({} nodes):", path.len());
-                for id in path {
+                for id in &path {
                     let title = dense_id_to_title.get(*id);
                     print!("{} -> ", title);
                 }
                 println!("END");
             }
-            _ => println!("No path found in CSR BFS (after {:.2?}).", elapsed_fwd),
+            SearchOutcome::NoPath => {
+                println!("No path found in CSR BFS (after {:.2?}).", elapsed_fwd)
+            }
+            SearchOutcome::Exhausted(limit) => {
+                println!(
+                    "CSR BFS stopped by {:?} (after {:.2?}).",
+                    limit, elapsed_fwd
+                )
+            }
         }
 
         println!("\n----------------------------\n");
@@ -1020,54 +2419,101 @@ pub fn bfs_interactive_session<G>(
 }
 
 use rand::Rng;
+
+fn print_timing_summary(label: &str, times: &[f64]) {
+    let mut sorted = times.to_vec();
+    sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+    let total: f64 = sorted.iter().sum();
+    let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p) as usize] * 1000.0;
+    println!(
+        "{:<8} total {:.2}s  mean {:.2}ms  p50 {:.2}ms  p90 {:.2}ms  p99 {:.2}ms  max {:.2}ms",
+        label,
+        total,
+        total / sorted.len() as f64 * 1000.0,
+        percentile(0.5),
+        percentile(0.9),
+        percentile(0.99),
+        percentile(1.0),
+    );
+}
+
+/// Runs the old hashmap search and the pooled search on the same random pairs
+/// and reports the timings of both (before / after).
 pub fn benchmark_random_bfs<G>(
     graph: &G,
-    redirect_targets_dense: &FxHashMap<u32, u32>,
+    redirect_targets_dense: &RedirectTargetsDenseMmap,
     num_pairs: usize,
     max_depth: u8,
-    redirects_passed_mmap: &RedirectsPassedMmap,
-    // redirects_passed_mmap: &FxHashMap<(u32, u32), u32>,
 ) where
     G: CsrGraphTrait,
 {
+    if num_pairs == 0 {
+        return;
+    }
+
     let start_time = Instant::now();
-    let mut times: Vec<f64> = Vec::with_capacity(num_pairs);
+    let mut times_hashmap: Vec<f64> = Vec::with_capacity(num_pairs);
+    let mut times_pooled: Vec<f64> = Vec::with_capacity(num_pairs);
 
     let mut rng = rand::rng();
     let n = graph.num_nodes();
+    let pool = SearchContextPool::new(n);
 
     for _ in 0..num_pairs {
         let start_dense = rng.random_range(0..n) as u32;
         let goal_dense = rng.random_range(0..n) as u32;
 
         // Resolve redirects
-        let start_id = redirect_targets_dense
-            .get(&start_dense)
-            .copied()
-            .unwrap_or(start_dense);
-        let goal_id = redirect_targets_dense
-            .get(&goal_dense)
-            .copied()
-            .unwrap_or(goal_dense);
+        let start_id = match redirect_targets_dense.get(start_dense) {
+            u32::MAX => start_dense,
+            redirect => redirect,
+        };
+        let goal_id = match redirect_targets_dense.get(goal_dense) {
+            u32::MAX => goal_dense,
+            redirect => redirect,
+        };
 
-        println!("\nRunning BFS: {} -> {}", start_id, goal_dense);
+        println!("\nRunning BFS: {} -> {}", start_id, goal_id);
 
         let start_time = Instant::now();
         let mut node_count = 0;
-        let paths = bi_bfs_csr::<G>(
+        let dag_hashmap =
+            bi_bfs_csr_hashmap::<G>(graph, start_id, goal_id, max_depth, &mut node_count);
+        let elapsed_hashmap = start_time.elapsed();
+        times_hashmap.push(elapsed_hashmap.as_secs_f64());
+
+        // the context is checked out per search, like the http handler does
+        let start_time = Instant::now();
+        let mut node_count = 0;
+        let dag_pooled = bi_bfs_csr::<G>(
             graph,
+            &mut pool.get(),
             start_id,
             goal_id,
-            max_depth,
-            redirects_passed_mmap,
+            &SearchLimits::with_max_depth(max_depth),
             &mut node_count,
         );
-        let elapsed = start_time.elapsed();
-        times.push(elapsed.as_secs_f64());
+        let elapsed_pooled = start_time.elapsed();
+        times_pooled.push(elapsed_pooled.as_secs_f64());
+
+        let num_paths_hashmap = dag_hashmap.map(|dag| dag.num_paths());
+        let num_paths_pooled = dag_pooled.found().map(|dag| dag.num_paths());
+        if num_paths_hashmap != num_paths_pooled {
+            println!(
+                "MISMATCH: hashmap found {:?} paths, pooled found {:?}",
+                num_paths_hashmap, num_paths_pooled
+            );
+        }
 
-        match paths {
-            Some(p) => println!("Found {} paths in {:.2?}", p.len(), elapsed),
-            None => println!("No path found (elapsed {:.2?})", elapsed),
+        match num_paths_pooled {
+            Some(num_paths) => println!(
+                "Found {} paths in {:.2?} (hashmap {:.2?})",
+                num_paths, elapsed_pooled, elapsed_hashmap
+            ),
+            None => println!(
+                "No path found (elapsed {:.2?}, hashmap {:.2?})",
+                elapsed_pooled, elapsed_hashmap
+            ),
         }
     }
     let elapsed = start_time.elapsed();
@@ -1075,11 +2521,17 @@ pub fn benchmark_random_bfs<G>(
         "Benchmark for {} pairs,  (elapsed {:.2?})",
         num_pairs, elapsed
     );
+    print_timing_summary("before", &times_hashmap);
+    print_timing_summary("after", &times_pooled);
+    let total_hashmap: f64 = times_hashmap.iter().sum();
+    let total_pooled: f64 = times_pooled.iter().sum();
+    println!("speedup: {:.2}x", total_hashmap / total_pooled);
 
     // Save times to CSV
     let mut file = File::create("bfs_times.csv").unwrap();
-    for t in &times {
-        writeln!(file, "{:.6}", t).unwrap();
+    writeln!(file, "hashmap_s,pooled_s").unwrap();
+    for (t_hashmap, t_pooled) in times_hashmap.iter().zip(&times_pooled) {
+        writeln!(file, "{:.6},{:.6}", t_hashmap, t_pooled).unwrap();
     }
 }
 
@@ -1108,3 +2560,84 @@ pub fn benchmark_random_bfs<G>(
 
 // find sources and sinks and paths between them
 // "Hello, World!" program in the map?
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // links must be sorted and without duplicates
+    fn csr_from_links(num_nodes: usize, links: &[(u32, u32)]) -> CsrGraph {
+        let to_csr = |links: &mut dyn Iterator<Item = (u32, u32)>| -> (Vec<u32>, Vec<u32>) {
+            let mut offsets = vec![0u32; num_nodes + 1];
+            let mut edges = Vec::new();
+            for (from, to) in links {
+                offsets[from as usize + 1] += 1;
+                edges.push(to);
+            }
+            for node in 0..num_nodes {
+                offsets[node + 1] += offsets[node];
+            }
+            (offsets, edges)
+        };
+        let (offsets, edges) = to_csr(&mut links.iter().copied());
+        let mut reversed: Vec<(u32, u32)> = links.iter().map(|&(from, to)| (to, from)).collect();
+        reversed.sort_unstable();
+        let (reverse_offsets, reverse_edges) = to_csr(&mut reversed.into_iter());
+
+        CsrGraph {
+            offsets,
+            edges,
+            reverse_offsets,
+            reverse_edges,
+        }
+    }
+
+    /// `bi_bfs_csr` with only parallel steps finds the same paths as with only sequential ones
+    #[test]
+    fn parallel_bfs_matches_sequential() {
+        for seed in 0..30 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let num_nodes = rng.random_range(2..5_000);
+            let avg_degree = rng.random_range(1..10);
+            let mut links: Vec<(u32, u32)> = (0..num_nodes * avg_degree)
+                .map(|_| {
+                    (
+                        rng.random_range(0..num_nodes) as u32,
+                        rng.random_range(0..num_nodes) as u32,
+                    )
+                })
+                .collect();
+            links.sort_unstable();
+            links.dedup();
+            let graph = csr_from_links(num_nodes, &links);
+
+            let mut sequential = SearchContext::new(num_nodes);
+            sequential.parallel_threshold = usize::MAX;
+            let mut parallel = SearchContext::new(num_nodes);
+            parallel.parallel_threshold = 0;
+
+            let limits = SearchLimits::default();
+            for _ in 0..20 {
+                let start = rng.random_range(0..num_nodes) as u32;
+                let goal = rng.random_range(0..num_nodes) as u32;
+                let search = |ctx: &mut SearchContext| -> Option<(u64, FxHashSet<Vec<u32>>)> {
+                    let mut node_count = 0;
+                    bi_bfs_csr(&graph, ctx, start, goal, &limits, &mut node_count)
+                        .found()
+                        .map(|dag| (dag.num_paths(), unresolved_paths(&dag).collect()))
+                };
+                assert_eq!(
+                    search(&mut sequential),
+                    search(&mut parallel),
+                    "seed {}: {} -> {} on {} nodes",
+                    seed,
+                    start,
+                    goal,
+                    num_nodes
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// src/search_context.rs
+// reusable scratch memory for the searches, so a search doesn't allocate hashmaps every time
+
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::AtomicU64;
+use std::sync::Mutex;
+
+use crate::search::PARALLEL_FRONTIER_THRESHOLD;
+
+// epochs live in the top 24 bits of a mark, depth in the low 8
+const EPOCH_LIMIT: u32 = 1 << 24;
+
+/// Dense per-node depths that are only valid for the epoch they were written in,
+/// so starting a new search is O(1) instead of clearing num_nodes entries.
+pub struct StampedDepths {
+    // epoch << 8 | depth, 0 is never a valid epoch
+    marks: Vec<u32>,
+    epoch: u32,
+}
+
+impl StampedDepths {
+    pub fn new(num_nodes: usize) -> Self {
+        StampedDepths {
+            marks: vec![0; num_nodes],
+            epoch: 1,
+        }
+    }
+
+    /// Forget every depth
+    pub fn reset(&mut self) {
+        self.epoch += 1;
+        // only clear for real once every ~16 million searches
+        if self.epoch == EPOCH_LIMIT {
+            self.marks.fill(0);
+            self.epoch = 1;
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, node: u32) -> Option<u8> {
+        let mark = self.marks[node as usize];
+        (mark >> 8 == self.epoch).then_some(mark as u8)
+    }
+
+    #[inline]
+    pub fn contains(&self, node: u32) -> bool {
+        self.marks[node as usize] >> 8 == self.epoch
+    }
+
+    #[inline]
+    pub fn set(&mut self, node: u32, depth: u8) {
+        self.marks[node as usize] = self.epoch << 8 | depth as u32;
+    }
+
+    /// Set the depth if the node hasn't been seen this epoch. Returns true if it was set.
+    #[inline]
+    pub fn insert(&mut self, node: u32, depth: u8) -> bool {
+        if self.contains(node) {
+            return false;
+        }
+        self.set(node, depth);
+        true
+    }
+}
+
+/// Everything a bidirectional search needs besides the graph.
+/// About 8 bytes per node, so these are pooled rather than made per search.
+pub struct SearchContext {
+    pub depth_fwd: StampedDepths,
+    pub depth_bwd: StampedDepths,
+    pub frontier_fwd: Vec<u32>,
+    pub frontier_bwd: Vec<u32>,
+    pub next_frontier: Vec<u32>,
+    // one bit per node for bottom-up steps, all zero between steps
+    pub frontier_bits: Vec<u64>,
+    // one bit per node for parallel steps, threads claim new nodes here. All zero between steps
+    pub claimed_bits: Vec<AtomicU64>,
+    // frontiers this big get expanded on every core
+    pub parallel_threshold: usize,
+}
+
+impl SearchContext {
+    pub fn new(num_nodes: usize) -> Self {
+        SearchContext {
+            depth_fwd: StampedDepths::new(num_nodes),
+            depth_bwd: StampedDepths::new(num_nodes),
+            frontier_fwd: Vec::new(),
+            frontier_bwd: Vec::new(),
+            next_frontier: Vec::new(),
+            frontier_bits: vec![0; num_nodes.div_ceil(64)],
+            claimed_bits: (0..num_nodes.div_ceil(64))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            parallel_threshold: PARALLEL_FRONTIER_THRESHOLD,
+        }
+    }
+
+    /// Start a new search, keeps the allocations
+    pub fn reset(&mut self) {
+        self.depth_fwd.reset();
+        self.depth_bwd.reset();
+        self.frontier_fwd.clear();
+        self.frontier_bwd.clear();
+        self.next_frontier.clear();
+    }
+}
+
+/// Idle search contexts for one graph. A context is handed to one thread at a time,
+/// so the pool only grows to the number of searches running at once.
+pub struct SearchContextPool {
+    num_nodes: usize,
+    idle: Mutex<Vec<SearchContext>>,
+}
+
+impl SearchContextPool {
+    pub fn new(num_nodes: usize) -> Self {
+        SearchContextPool {
+            num_nodes,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Take an idle context, or make one if they're all in use. It goes back to the pool on drop.
+    pub fn get(&self) -> PooledSearchContext<'_> {
+        let ctx = self.idle.lock().unwrap().pop();
+        let ctx = ctx.unwrap_or_else(|| SearchContext::new(self.num_nodes));
+        PooledSearchContext {
+            pool: self,
+            ctx: Some(ctx),
+        }
+    }
+}
+
+pub struct PooledSearchContext<'a> {
+    pool: &'a SearchContextPool,
+    // only None while being dropped
+    ctx: Option<SearchContext>,
+}
+
+impl Deref for PooledSearchContext<'_> {
+    type Target = SearchContext;
+
+    fn deref(&self) -> &SearchContext {
+        self.ctx.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledSearchContext<'_> {
+    fn deref_mut(&mut self) -> &mut SearchContext {
+        self.ctx.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledSearchContext<'_> {
+    fn drop(&mut self) {
+        if let Some(ctx) = self.ctx.take() {
+            self.pool.idle.lock().unwrap().push(ctx);
+        }
+    }
+}
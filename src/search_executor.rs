@@ -0,0 +1,100 @@
+// src/search_executor.rs
+// searches are blocking cpu work, so they run on their own threads instead of the tokio workers
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Returned by `try_submit` when the queue is full
+#[derive(Debug)]
+pub struct QueueFull;
+
+#[derive(Default)]
+struct Counters {
+    // submitted but not picked up by a thread yet
+    queued: AtomicUsize,
+    // running on a thread right now
+    in_flight: AtomicUsize,
+}
+
+/// Fixed number of search threads fed by a bounded queue.
+pub struct SearchExecutor {
+    sender: SyncSender<Job>,
+    counters: Arc<Counters>,
+}
+
+impl SearchExecutor {
+    pub fn new(num_threads: usize, queue_limit: usize) -> Self {
+        // a zero sized sync_channel would only accept jobs when a thread is already waiting
+        let (sender, receiver) = sync_channel::<Job>(queue_limit.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let counters = Arc::new(Counters::default());
+
+        for i in 0..num_threads.max(1) {
+            let receiver = receiver.clone();
+            let counters = counters.clone();
+            thread::Builder::new()
+                .name(format!("search-{}", i))
+                .spawn(move || worker_loop(&receiver, &counters))
+                .expect("failed to spawn search thread");
+        }
+
+        SearchExecutor { sender, counters }
+    }
+
+    /// Queue a job, or fail right away if the queue is full.
+    /// The receiver gets the job's result, or an error if the job panicked.
+    pub fn try_submit<T, F>(&self, job: F) -> Result<oneshot::Receiver<T>, QueueFull>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            // the requester might have gone away, nothing to do then
+            let _ = tx.send(job());
+        });
+
+        self.counters.queued.fetch_add(1, Ordering::Relaxed);
+        match self.sender.try_send(job) {
+            Ok(()) => Ok(rx),
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.counters.queued.fetch_sub(1, Ordering::Relaxed);
+                Err(QueueFull)
+            }
+        }
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.counters.queued.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.counters.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+fn worker_loop(receiver: &Mutex<Receiver<Job>>, counters: &Counters) {
+    loop {
+        // only hold the lock while waiting for a job, not while running it
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return, // executor dropped
+        };
+        counters.queued.fetch_sub(1, Ordering::Relaxed);
+        counters.in_flight.fetch_add(1, Ordering::Relaxed);
+
+        // a panicking search drops its result sender (the handler sees an error), the thread lives on
+        if catch_unwind(AssertUnwindSafe(job)).is_err() {
+            tracing::error!("search job panicked");
+        }
+
+        counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,244 @@
+// src/simulate.rs
+// plays the wiki game like a person would: one click at a time, only seeing the current page's links
+
+use rand::Rng;
+use rayon::prelude::*;
+use rustc_hash::FxHashSet;
+
+use crate::graph::*;
+use crate::mmap_structs::*;
+use crate::paths::DataDir;
+use crate::search::{bi_bfs_csr_depth_only, SearchLimits, SearchOutcome};
+use crate::search_context::SearchContext;
+
+/// How the simulated player picks the next link
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Strategy {
+    /// Any link
+    Random,
+    /// The link to the page with the most links into it
+    Greedy,
+    /// The link whose title shares the most words with the goal's title
+    Tokens,
+}
+
+/// Results of every game one strategy played
+pub struct StrategyReport {
+    pub strategy: Strategy,
+    pub games: usize,
+    pub wins: usize,
+    // clicks in the games that reached the goal
+    pub median_clicks: Option<u32>,
+    // clicks on top of the bfs distance, also only for wins
+    pub median_extra_clicks: Option<u32>,
+}
+
+/// Play one game, returns the number of clicks if the goal was reached within `max_clicks`.
+/// Every strategy clicks the goal as soon as it's on the page and avoids pages it has already
+/// been to while it has a choice.
+pub fn play_game<G: CsrGraphTrait>(
+    graph: &G,
+    dense_id_to_title: &DenseIdToTitleMmap,
+    start: u32,
+    goal: u32,
+    strategy: Strategy,
+    max_clicks: u32,
+    rng: &mut impl Rng,
+) -> Option<u32> {
+    let goal_tokens = title_tokens(dense_id_to_title.get(goal));
+    let mut visited: FxHashSet<u32> = FxHashSet::default();
+    let mut page = start;
+    let mut links: Vec<u32> = Vec::new();
+
+    for clicks in 0..=max_clicks {
+        if page == goal {
+            return Some(clicks);
+        }
+        visited.insert(page);
+
+        links.clear();
+        links.extend(graph.neighbors(page));
+        if links.contains(&goal) {
+            page = goal;
+            continue;
+        }
+        // back at a page where everything was seen already, wander anywhere
+        let unseen: Vec<u32> = links
+            .iter()
+            .copied()
+            .filter(|link| !visited.contains(link))
+            .collect();
+        let choices = if unseen.is_empty() { &links } else { &unseen };
+        if choices.is_empty() {
+            // dead end
+            return None;
+        }
+
+        page = match strategy {
+            Strategy::Random => choices[rng.random_range(0..choices.len())],
+            Strategy::Greedy => pick_best(choices, rng, |link| graph.reverse_degree(link)),
+            Strategy::Tokens => pick_best(choices, rng, |link| {
+                let shared = title_tokens(dense_id_to_title.get(link))
+                    .intersection(&goal_tokens)
+                    .count();
+                // same overlap, the better linked page is more likely to lead somewhere
+                (shared, graph.reverse_degree(link))
+            }),
+        };
+    }
+    None
+}
+
+// highest scoring link, ties broken at random so repeated games don't all go the same way
+fn pick_best<K: Ord>(choices: &[u32], rng: &mut impl Rng, score: impl Fn(u32) -> K) -> u32 {
+    let scores: Vec<K> = choices.iter().map(|&link| score(link)).collect();
+    let best = scores.iter().max().expect("choices aren't empty");
+    let tied: Vec<u32> = choices
+        .iter()
+        .zip(&scores)
+        .filter(|(_, score)| *score == best)
+        .map(|(&link, _)| link)
+        .collect();
+    tied[rng.random_range(0..tied.len())]
+}
+
+// lowercase words of a title, underscores count as spaces
+fn title_tokens(title: &str) -> FxHashSet<String> {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+/// `games_per_pair` games per pair and strategy. `pairs` holds (start, goal, bfs distance).
+pub fn simulate<G: CsrGraphTrait>(
+    graph: &G,
+    dense_id_to_title: &DenseIdToTitleMmap,
+    pairs: &[(u32, u32, u8)],
+    strategies: &[Strategy],
+    games_per_pair: usize,
+    max_clicks: u32,
+) -> Vec<StrategyReport> {
+    strategies
+        .iter()
+        .map(|&strategy| {
+            // (clicks, bfs distance) of every game that reached the goal
+            let wins: Vec<(u32, u8)> = pairs
+                .par_iter()
+                .flat_map_iter(|&(start, goal, distance)| {
+                    let mut rng = rand::rng();
+                    (0..games_per_pair)
+                        .filter_map(|_| {
+                            play_game(
+                                graph,
+                                dense_id_to_title,
+                                start,
+                                goal,
+                                strategy,
+                                max_clicks,
+                                &mut rng,
+                            )
+                        })
+                        .map(|clicks| (clicks, distance))
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+
+            let mut clicks: Vec<u32> = wins.iter().map(|&(clicks, _)| clicks).collect();
+            let mut extra_clicks: Vec<u32> = wins
+                .iter()
+                .map(|&(clicks, distance)| clicks - distance as u32)
+                .collect();
+            StrategyReport {
+                strategy,
+                games: pairs.len() * games_per_pair,
+                wins: wins.len(),
+                median_clicks: median(&mut clicks),
+                median_extra_clicks: median(&mut extra_clicks),
+            }
+        })
+        .collect()
+}
+
+fn median(values: &mut [u32]) -> Option<u32> {
+    if values.is_empty() {
+        return None;
+    }
+    let mid = values.len() / 2;
+    Some(*values.select_nth_unstable(mid).1)
+}
+
+/// Simulate the strategies on random pairs that have a path and print how they do
+/// compared to the shortest path
+pub fn run_simulate_cli(
+    data: &DataDir,
+    num_pairs: usize,
+    games_per_pair: usize,
+    max_clicks: u32,
+    strategies: &[Strategy],
+) -> anyhow::Result<()> {
+    let graph = load_csr_graph_mmap(data)?;
+    let dense_id_to_title = load_dense_id_to_title_mmap(data)?;
+    let redirect_targets_dense = load_redirect_targets_dense_mmap(data)?;
+    let num_nodes = graph.num_nodes();
+
+    // starts and goals are real pages, like the api resolves them
+    let mut rng = rand::rng();
+    let mut ctx = SearchContext::new(num_nodes);
+    let limits = SearchLimits::with_max_depth(50);
+    let mut pairs: Vec<(u32, u32, u8)> = Vec::with_capacity(num_pairs);
+    let mut tries = 0;
+    while pairs.len() < num_pairs {
+        tries += 1;
+        if tries > num_pairs * 100 {
+            anyhow::bail!("couldn't find {} pairs with a path", num_pairs);
+        }
+        let start = rng.random_range(0..num_nodes) as u32;
+        let goal = rng.random_range(0..num_nodes) as u32;
+        if start == goal
+            || redirect_targets_dense.get(start) != u32::MAX
+            || redirect_targets_dense.get(goal) != u32::MAX
+        {
+            continue;
+        }
+        if let SearchOutcome::Found(distance) =
+            bi_bfs_csr_depth_only(&graph, &mut ctx, start, goal, &limits, None)
+        {
+            pairs.push((start, goal, distance));
+        }
+    }
+
+    let mut distances: Vec<u32> = pairs.iter().map(|&(_, _, d)| d as u32).collect();
+    println!(
+        "{} pairs, median bfs distance {}, {} games each, {} clicks at most",
+        pairs.len(),
+        median(&mut distances).unwrap_or(0),
+        games_per_pair,
+        max_clicks
+    );
+
+    let reports = simulate(
+        &graph,
+        &dense_id_to_title,
+        &pairs,
+        strategies,
+        games_per_pair,
+        max_clicks,
+    );
+    let show = |value: Option<u32>| value.map_or("-".to_string(), |value| value.to_string());
+    println!(
+        "{:<8} {:>8} {:>14} {:>14}",
+        "strategy", "success", "median clicks", "median extra"
+    );
+    for report in reports {
+        println!(
+            "{:<8} {:>7.1}% {:>14} {:>14}",
+            format!("{:?}", report.strategy).to_lowercase(),
+            report.wins as f64 / report.games.max(1) as f64 * 100.0,
+            show(report.median_clicks),
+            show(report.median_extra_clicks)
+        );
+    }
+    Ok(())
+}
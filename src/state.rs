@@ -1,18 +1,145 @@
+use crate::builders::Manifest;
 use crate::config::EnvironmentVariables;
-use crate::graph::CsrGraphMmap;
+use crate::graph::{CsrGraphTrait, GraphBackend};
 use crate::mmap_structs::*;
-use std::sync::Arc;
+use crate::paths::DataDir;
+use crate::puzzle::{Difficulty, PuzzleOutput};
+use crate::search_context::SearchContextPool;
+use crate::search_executor::SearchExecutor;
+use anyhow::Context;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// (wiki, date, difficulty) -> that day's puzzle, none when the band had no pair.
+/// Generated by the first request for it, the others wait for that one.
+pub type DailyPuzzles =
+    Mutex<HashMap<(String, NaiveDate, Difficulty), Arc<OnceCell<Option<PuzzleOutput>>>>>;
+
+/// One wiki's graph and lookup tables, everything built into one data dir
+pub struct Dataset {
+    // enwiki, simplewiki..., what requests pass as `wiki`
+    pub wiki: String,
+    pub title_to_dense_id: TitleToDenseIdMmap,
+    pub dense_id_to_title: DenseIdToTitleMmap,
+    pub orig_to_dense_id: OrigToDenseIdMmap,
+    pub dense_id_to_orig: DenseIdToOrigMmap,
+    pub redirects_passed: RedirectsPassedMmap,
+    pub redirect_targets_dense: RedirectTargetsDenseMmap,
+    pub csr_graph: GraphBackend,
+    pub hub_degrees: HubDegreesMmap,
+    // none until `--build-scc` has been run
+    pub scc: Option<SccMmap>,
+    // none until `--build-landmarks` has been run
+    pub landmarks: Option<LandmarksMmap>,
+    // sized for this graph, so each wiki has its own
+    pub search_contexts: SearchContextPool,
+}
+
+impl Dataset {
+    pub fn load(wiki: &str, data: &DataDir, compressed_graph: bool) -> anyhow::Result<Self> {
+        // a stage that died halfway may have left its inputs gone or relabeled
+        if let Some(stage) = Manifest::load(data)?.and_then(|manifest| manifest.running) {
+            anyhow::bail!(
+                "the build in {} stopped during {:?}, run it again",
+                data.path().display(),
+                stage
+            );
+        }
+        // reading every page at startup would take too long
+        tracing::info!(
+            "{}: checksums in {} aren't checked on load, run --verify-data after copying it",
+            wiki,
+            data.path().display()
+        );
+        let csr_graph = load_graph_backend(data, compressed_graph)?;
+        let hub_degrees = load_or_build_hub_degrees_mmap(data, &csr_graph)?;
+        let scc = match load_scc_mmap(data) {
+            Ok(scc) => Some(scc),
+            Err(e) => {
+                tracing::warn!(
+                    "{}: no scc files, run with --build-scc to answer unreachable pairs instantly: {}",
+                    wiki,
+                    e
+                );
+                None
+            }
+        };
+        let landmarks = match load_landmarks_mmap(data) {
+            Ok(landmarks) => Some(landmarks),
+            Err(e) => {
+                tracing::warn!(
+                    "{}: no landmark files, run with --build-landmarks for distance estimates: {}",
+                    wiki,
+                    e
+                );
+                None
+            }
+        };
+        let search_contexts = SearchContextPool::new(csr_graph.num_nodes());
+
+        let dataset = Dataset {
+            wiki: wiki.to_string(),
+            title_to_dense_id: load_title_to_dense_id_mmap(data)?,
+            dense_id_to_title: load_dense_id_to_title_mmap(data)?,
+            orig_to_dense_id: load_orig_to_dense_id_mmap(data)?,
+            dense_id_to_orig: load_dense_id_to_orig_mmap(data)?,
+            redirects_passed: load_redirects_passed_mmap(data)?,
+            redirect_targets_dense: load_redirect_targets_dense_mmap(data)?,
+            csr_graph,
+            hub_degrees,
+            scc,
+            landmarks,
+            search_contexts,
+        };
+        dataset.check_num_pages()?;
+        Ok(dataset)
+    }
+
+    /// Every file indexed by dense id has to agree with the graph on the number of pages,
+    /// otherwise lookups give the wrong titles or index out of bounds
+    fn check_num_pages(&self) -> anyhow::Result<()> {
+        let num_nodes = self.csr_graph.num_nodes() as u64;
+        let mut counts = vec![
+            (&self.dense_id_to_title.offsets, num_nodes + 1),
+            (&self.title_to_dense_id.dense_ids, num_nodes),
+            (&self.orig_to_dense_id.dense_ids, num_nodes),
+            (&self.dense_id_to_orig.orig_ids, num_nodes),
+            (&self.redirects_passed.offsets, num_nodes + 1),
+            (&self.redirect_targets_dense.mmap, num_nodes),
+        ];
+        if let Some(scc) = &self.scc {
+            counts.push((&scc.component_ids, num_nodes));
+        }
+        if let Some(landmarks) = &self.landmarks {
+            let num_landmarks = landmarks.num_landmarks() as u64;
+            counts.push((&landmarks.dist_from, num_nodes * num_landmarks));
+        }
+        for (view, expected) in counts {
+            view.expect_count(expected)
+                .with_context(|| format!("{}: the graph has {} pages", self.wiki, num_nodes))?;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
-    pub title_to_dense_id: Arc<TitleToDenseIdMmap>,
-    pub dense_id_to_title: Arc<DenseIdToTitleMmap>,
-    pub orig_to_dense_id: Arc<OrigToDenseIdMmap>,
-    pub dense_id_to_orig: Arc<DenseIdToOrigMmap>,
-    pub redirects_passed: Arc<RedirectsPassedMmap>,
-    pub redirect_targets_dense: Arc<RedirectTargetsDenseMmap>,
-    pub csr_graph: Arc<CsrGraphMmap>,
+    pub datasets: BTreeMap<String, Arc<Dataset>>,
+    // used by requests without a `wiki`, the first one in DATASETS
+    pub default_wiki: String,
+    // shared by every dataset, the thread and queue limits are for the whole server
+    pub search_executor: Arc<SearchExecutor>,
     pub redis_pool: deadpool_redis::Pool,
     pub sqlite_pool: sqlx::SqlitePool,
     pub env: EnvironmentVariables,
+    pub daily_puzzles: Arc<DailyPuzzles>,
+}
+
+impl AppState {
+    /// The dataset for a request's `wiki`, the default one when it didn't give one
+    pub fn dataset(&self, wiki: Option<&str>) -> Option<&Arc<Dataset>> {
+        self.datasets.get(wiki.unwrap_or(&self.default_wiki))
+    }
 }
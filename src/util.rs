@@ -1,10 +1,14 @@
+use crate::paths::DataDir;
+use anyhow::Context;
 use bitcode::Decode;
 use bitcode::Encode;
 use bytemuck::cast_slice;
 use memmap2::Mmap;
-use std::fs::create_dir_all;
+use std::fs::{create_dir_all, rename};
 use std::io::Read;
+use std::ops::Deref;
 use std::path::Path;
+use std::sync::Arc;
 use std::{
     fs::File,
     io::{BufReader, BufWriter, Write},
@@ -48,10 +52,18 @@ pub fn unescape_sql_string(s: &str) -> String {
     result
 }
 
-pub fn save_to_file<T: Encode>(data: &T, path: &str) -> anyhow::Result<()> {
-    println!("Encoding and saving to file");
-    let encoded = bitcode::encode(data); // Encode into Vec<u8>
+/// Write to `path`.tmp and rename it over `path` once it's all on disk, so a build that
+/// fails or gets killed halfway never leaves a truncated file under the real name
+pub fn write_atomically(bytes: &[u8], path: &str) -> anyhow::Result<()> {
+    write_atomically_with(path, |writer| writer.write_all(bytes))
+}
 
+/// Like `write_atomically` but hands the writer to `write`, so a file made of several
+/// pieces goes straight to disk without being joined into one buffer first
+pub fn write_atomically_with(
+    path: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> anyhow::Result<()> {
     // Ensure the parent directory exists
     if let Some(parent) = Path::new(path).parent() {
         if !parent.as_os_str().is_empty() {
@@ -59,15 +71,24 @@ pub fn save_to_file<T: Encode>(data: &T, path: &str) -> anyhow::Result<()> {
         }
     }
 
-    let file = File::create(path)?;
+    let tmp_path = format!("{}.tmp", path);
+    let file = File::create(&tmp_path)?;
     let mut writer = BufWriter::with_capacity(128 * 1024, file);
-
-    writer.write_all(&encoded)?;
-    writer.flush()?;
-
+    write(&mut writer)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    rename(&tmp_path, path)?;
     Ok(())
 }
 
+pub fn save_to_file<T: Encode>(data: &T, path: &str) -> anyhow::Result<()> {
+    println!("Encoding and saving to file");
+    let encoded = bitcode::encode(data); // Encode into Vec<u8>
+    write_atomically(&encoded, path)
+}
+
 pub fn load_from_file<T: for<'a> Decode<'a>>(path: &str) -> anyhow::Result<T> {
     let file = File::open(path)?;
     let mut reader = BufReader::with_capacity(128 * 1024, file);
@@ -81,56 +102,301 @@ pub fn load_from_file<T: for<'a> Decode<'a>>(path: &str) -> anyhow::Result<T> {
     Ok(decoded)
 }
 
-/// Write a Vec<u32> as raw little-endian bytes to disk.
+// every mmap file starts with a header, 64 bytes so the data after it stays 8 byte aligned
+pub const HEADER_LEN: usize = 64;
+const MAGIC: &[u8; 8] = b"WGSOLVER";
+// bump when the layout of any mmap file changes, older files then fail to load
+pub const FORMAT_VERSION: u32 = 1;
+
+/// What an mmap file holds, the value is the size in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    U8 = 1,
+    U32 = 4,
+    U64 = 8,
+}
+
+pub trait MmapElement: bytemuck::Pod {
+    const TYPE: ElementType;
+}
+
+impl MmapElement for u8 {
+    const TYPE: ElementType = ElementType::U8;
+}
+
+impl MmapElement for u32 {
+    const TYPE: ElementType = ElementType::U32;
+}
+
+impl MmapElement for u64 {
+    const TYPE: ElementType = ElementType::U64;
+}
+
+/// Start of every mmap file, little endian
+#[derive(Debug, Clone, Copy)]
+pub struct FileHeader {
+    pub version: u32,
+    pub element_size: u32,
+    pub count: u64,
+    // DataDir::dataset_id of the build that wrote it, 0 when unknown
+    pub dataset_id: u64,
+    pub checksum: u64,
+}
+
+impl FileHeader {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[0..8].copy_from_slice(MAGIC);
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.element_size.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.count.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.dataset_id.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Header of a whole file, checked against its length but not its checksum
+    pub fn parse(bytes: &[u8], path: &str) -> anyhow::Result<FileHeader> {
+        if bytes.len() < HEADER_LEN || &bytes[0..8] != MAGIC {
+            anyhow::bail!(
+                "{} has no header, it's from an older build, rebuild it",
+                path
+            );
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        let header = FileHeader {
+            version: u32_at(8),
+            element_size: u32_at(12),
+            count: u64_at(16),
+            dataset_id: u64_at(24),
+            checksum: u64_at(32),
+        };
+        if header.version != FORMAT_VERSION {
+            anyhow::bail!(
+                "{} is format version {}, this build reads {}, rebuild it",
+                path,
+                header.version,
+                FORMAT_VERSION
+            );
+        }
+        let expected_len = header.count * header.element_size as u64;
+        if (bytes.len() - HEADER_LEN) as u64 != expected_len {
+            anyhow::bail!(
+                "{} should have {} bytes of data, has {}, it's truncated or corrupt",
+                path,
+                expected_len,
+                bytes.len() - HEADER_LEN
+            );
+        }
+        Ok(header)
+    }
+}
+
+/// fnv-1a over 8 byte words rather than bytes, fast enough for the multi-gigabyte edge files
+pub fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut words = bytes.chunks_exact(8);
+    for word in &mut words {
+        hash ^= u64::from_le_bytes(word.try_into().unwrap());
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    for &byte in words.remainder() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn write_mmap_file<T: MmapElement>(v: &[T], data: &DataDir, name: &str) -> anyhow::Result<()> {
+    // cast_slice is safe if T is Pod (it is) and we remain on same arch.
+    let bytes: &[u8] = cast_slice(v);
+    let header = FileHeader {
+        version: FORMAT_VERSION,
+        element_size: T::TYPE as u32,
+        count: v.len() as u64,
+        dataset_id: data.dataset_id(),
+        checksum: checksum(bytes),
+    };
+    write_atomically_with(&data.file(name), |writer| {
+        writer.write_all(&header.to_bytes())?;
+        writer.write_all(bytes)
+    })
+}
+
+/// Write a Vec<u32> as raw little-endian bytes after a header, to `name` in the data dir.
 /// For portability/AArch64 differences you can write u32::to_le_bytes in a loop,
 /// but this fast path assumes native little-endian.
-pub fn write_u32_vec_to_file(v: &Vec<u32>, path: &str) -> anyhow::Result<()> {
+pub fn write_u32_vec_to_file(v: &Vec<u32>, data: &DataDir, name: &str) -> anyhow::Result<()> {
     println!("Writing u32 and saving to file");
+    write_mmap_file(v, data, name)
+}
 
-    // Ensure the parent directory exists
-    if let Some(parent) = Path::new(path).parent() {
-        if !parent.as_os_str().is_empty() {
-            create_dir_all(parent)?;
+pub fn write_u64_vec_to_file(v: &[u64], data: &DataDir, name: &str) -> anyhow::Result<()> {
+    println!("Writing u64 and saving to file");
+    write_mmap_file(v, data, name)
+}
+
+pub fn write_u8_vec_to_file(v: &[u8], data: &DataDir, name: &str) -> anyhow::Result<()> {
+    println!("Writing u8 vector to file");
+    write_mmap_file(v, data, name)
+}
+
+/// A mapped file without its header, derefs to the data bytes. The mmap is the file's own,
+/// or a whole bundle shared by every view into it.
+pub struct MmapView {
+    mmap: Arc<Mmap>,
+    start: usize,
+    end: usize,
+    pub header: FileHeader,
+    // for error messages
+    pub path: String,
+}
+
+impl MmapView {
+    /// Error unless it has `expected` elements, for files that have to agree with each other
+    pub fn expect_count(&self, expected: u64) -> anyhow::Result<()> {
+        if self.header.count != expected {
+            anyhow::bail!(
+                "{} has {} entries, expected {}, the data files are from different builds",
+                self.path,
+                self.header.count,
+                expected
+            );
         }
+        Ok(())
     }
 
-    let mut file = File::create(path)?;
-    // cast_slice is safe if u32 is Pod (it is) and we remain on same arch.
-    let bytes: &[u8] = cast_slice(v.as_slice());
-    file.write_all(bytes)?;
-    file.sync_all()?;
-    Ok(())
+    /// Last element of an offsets file, the length of what it indexes into
+    pub fn last_offset(&self) -> u64 {
+        match self.header.element_size {
+            4 => mmap_as_u32_slice(self)
+                .last()
+                .map_or(0, |&offset| offset as u64),
+            8 => mmap_as_u64_slice(self).last().copied().unwrap_or(0),
+            _ => self.last().map_or(0, |&offset| offset as u64),
+        }
+    }
 }
 
-pub fn write_u8_vec_to_file(v: &Vec<u8>, path: &str) -> anyhow::Result<()> {
-    println!("Writing u8 vector to file");
+impl Deref for MmapView {
+    type Target = [u8];
 
-    // Ensure the parent directory exists
-    if let Some(parent) = Path::new(path).parent() {
-        if !parent.as_os_str().is_empty() {
-            create_dir_all(parent)?;
+    fn deref(&self) -> &[u8] {
+        &self.mmap[self.start..self.end]
+    }
+}
+
+/// Memory-map `name` in the data dir (or find it in the bundle), after checking its header:
+/// the version, that it holds T, that the length matches and that it's from the same build
+/// as the rest of the folder. The checksum is only checked by `verify_checksums`, it would
+/// read every page of the file.
+pub fn mmap_file<T: MmapElement>(data: &DataDir, name: &str) -> anyhow::Result<MmapView> {
+    let path = data.file(name);
+    let (mmap, start, len) = match data.bundle() {
+        Some(bundle) => {
+            let (start, len) = bundle
+                .section(name)
+                .ok_or_else(|| anyhow::anyhow!("{} isn't in the bundle", path))?;
+            (bundle.mmap.clone(), start, len)
+        }
+        None => {
+            let file = File::open(&path).with_context(|| format!("opening {}", path))?;
+            // Safety: mapping readonly file
+            let mmap = unsafe { Mmap::map(&file)? };
+            let len = mmap.len();
+            (Arc::new(mmap), 0, len)
         }
+    };
+    let header = FileHeader::parse(&mmap[start..start + len], &path)?;
+    if header.element_size != T::TYPE as u32 {
+        anyhow::bail!(
+            "{} holds {} byte elements, expected {:?}",
+            path,
+            header.element_size,
+            T::TYPE
+        );
+    }
+    let dataset_id = data.dataset_id();
+    if header.dataset_id != 0 && dataset_id != 0 && header.dataset_id != dataset_id {
+        anyhow::bail!(
+            "{} is from another build than the rest of {}, rebuild it",
+            path,
+            data.path().display()
+        );
     }
+    Ok(MmapView {
+        mmap,
+        start: start + HEADER_LEN,
+        end: start + len,
+        header,
+        path,
+    })
+}
 
-    let mut file = File::create(path)?;
-    file.write_all(v)?;
-    file.sync_all()?;
-    Ok(())
+/// True for files written by the write_*_vec_to_file functions
+pub fn has_file_header(path: &Path) -> anyhow::Result<bool> {
+    let mut magic = [0u8; 8];
+    let mut file = File::open(path)?;
+    Ok(file.read_exact(&mut magic).is_ok() && &magic == MAGIC)
 }
 
-/// Memory-map file and return the Mmap object.
-pub fn mmap_file(path: &str) -> anyhow::Result<Mmap> {
-    let file = File::open(path)?;
-    // Safety: mapping readonly file
-    let mmap = unsafe { Mmap::map(&file)? };
-    Ok(mmap)
+/// Check the checksum of every mmap file in the data dir or bundle, returns the number that failed
+pub fn verify_checksums(data: &DataDir) -> anyhow::Result<usize> {
+    let mut failed = 0;
+    let mut verify = |path: &str, bytes: &[u8]| {
+        // the bitcode files and manifest.json have no header
+        if bytes.len() < HEADER_LEN || &bytes[0..8] != MAGIC {
+            return;
+        }
+        match FileHeader::parse(bytes, path) {
+            Ok(header) if header.checksum == checksum(&bytes[HEADER_LEN..]) => {
+                println!("ok   {}", path)
+            }
+            Ok(_) => {
+                println!("BAD  {} (checksum)", path);
+                failed += 1;
+            }
+            Err(e) => {
+                println!("BAD  {} ({})", path, e);
+                failed += 1;
+            }
+        }
+    };
+
+    if let Some(bundle) = data.bundle() {
+        for (name, bytes) in bundle.sections() {
+            verify(&data.file(name), bytes);
+        }
+        return Ok(failed);
+    }
+    let mut dirs = vec![data.path().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let file = File::open(&path)?;
+            // Safety: mapping readonly file
+            let mmap = unsafe { Mmap::map(&file)? };
+            verify(&path.to_string_lossy(), &mmap);
+        }
+    }
+    Ok(failed)
 }
 
-/// Cast a Mmap (bytes) to &[u32]. Caller must ensure alignment and length multiple of 4.
+/// Cast mmap bytes to &[u32]. Caller must ensure alignment and length multiple of 4.
 /// This returns a runtime slice, not a stored reference into the struct.
-pub fn mmap_as_u32_slice(mmap: &Mmap) -> &'_ [u32] {
+pub fn mmap_as_u32_slice(bytes: &[u8]) -> &'_ [u32] {
     // bytemuck::cast_slice will panic if the length is not a multiple of u32
-    bytemuck::cast_slice::<u8, u32>(&mmap[..])
+    bytemuck::cast_slice::<u8, u32>(bytes)
+}
+
+/// Cast mmap bytes to &[u64], same rules as mmap_as_u32_slice with 8 bytes
+pub fn mmap_as_u64_slice(bytes: &[u8]) -> &'_ [u64] {
+    bytemuck::cast_slice::<u8, u64>(bytes)
 }
 
 use axum::http::StatusCode;